@@ -1,22 +1,29 @@
 use crate::fsutils::{compute_checksum, size_from_meta};
 use crate::hash::file_md5;
-use crate::objects::{Object, Oid, Tree, TreeEntry};
-use crate::odb::Odb;
+use crate::objects::{Normalization, Object, Oid, Tree, TreeEntry, TreeError};
+use crate::odb::{Odb, oid_to_path, path_to_oid};
 use crate::state::{State, StateError, StateHash, StateValue};
 use crate::timeutils::unix_time;
 use camino::{FromPathBufError, FromPathError, Utf8Path, Utf8PathBuf};
 use ignore;
 use ignore::gitignore::Gitignore;
+use itertools::Itertools;
 use jwalk::{Parallelism, WalkDir};
 use log::debug;
+use rayon::iter::Either;
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
-use std::path::StripPrefixError;
+use std::path::{Path, PathBuf, StripPrefixError};
+use std::str::FromStr;
 use std::time::Instant;
 struct FileInfo {
     checksum: String,
     path: Utf8PathBuf,
     size: u64,
+    /// `(device, inode)` of files with more than one link.
+    inode: Option<(u64, u64)>,
 }
 
 use thiserror::Error as ThisError;
@@ -35,6 +42,87 @@ pub enum BuildError {
     FromPathError(#[from] FromPathError),
     #[error(transparent)]
     StripPrefixError(#[from] StripPrefixError),
+    #[error(transparent)]
+    TreeError(#[from] TreeError),
+    #[error("'{}' is a symlink", .0.display())]
+    Symlink(PathBuf),
+}
+
+/// What to do with symlinks found while walking a directory. Symlinks that
+/// resolve into the odb are always recorded as the object they point to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Build the file or directory the symlink points to.
+    #[default]
+    Follow,
+    /// Leave symlinks out of the tree.
+    Skip,
+    /// Fail on symlinks, or report them with `continue_on_error`.
+    Error,
+}
+
+#[derive(Debug, ThisError)]
+#[error("invalid symlink policy '{0}', expected 'follow', 'skip' or 'error'")]
+pub struct InvalidSymlinkPolicy(String);
+
+impl FromStr for SymlinkPolicy {
+    type Err = InvalidSymlinkPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follow" => Ok(Self::Follow),
+            "skip" => Ok(Self::Skip),
+            "error" => Ok(Self::Error),
+            _ => Err(InvalidSymlinkPolicy(s.to_owned())),
+        }
+    }
+}
+
+/// How `build` treats files it cannot read and symlinks.
+///
+/// Hardlinks into the odb are only recorded as their object without being
+/// read if `State` has a record for their path, e.g. from the `add` that
+/// linked them. Without a state, or for paths never recorded in it, they are
+/// hashed like any other file.
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    /// Record unreadable files in the report and build the rest instead of
+    /// failing on the first error.
+    pub continue_on_error: bool,
+    pub symlinks: SymlinkPolicy,
+}
+
+/// Paths on disk of files, keyed by the normalized relpath they are recorded
+/// under in a tree.
+pub type Sources = HashMap<Utf8PathBuf, Utf8PathBuf>;
+
+/// Files left out of a build instead of failing it, and where the files
+/// recorded under another name are on disk.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    /// Files whose names are not valid UTF-8, which a tree cannot record.
+    pub non_utf8: Vec<PathBuf>,
+    /// Paths that could not be walked or hashed, sorted by path. Only filled
+    /// with [`BuildOptions::continue_on_error`].
+    pub errors: Vec<(PathBuf, BuildError)>,
+    /// Paths on disk of the files whose relpath was normalized, keyed by the
+    /// relpath in the tree, for [`crate::transfer`] to read them from.
+    pub sources: Sources,
+}
+
+impl BuildReport {
+    /// Whether no file was left out of the build.
+    pub fn is_empty(&self) -> bool {
+        self.non_utf8.is_empty() && self.errors.is_empty()
+    }
+}
+
+enum Collected {
+    File(FileInfo),
+    NonUtf8(PathBuf),
+    Error(PathBuf, BuildError),
+    /// A symlink into the odb, with the oid of the object it points to.
+    Cached(FileInfo, Oid),
 }
 
 #[inline]
@@ -80,21 +168,66 @@ impl FileInfo {
         };
         let size = size_from_meta(meta);
         let checksum = compute_checksum(ut, ino, size);
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+        };
+        #[cfg(not(unix))]
+        let inode = None;
         Ok(Self {
             checksum,
             size,
             path: path.to_path_buf(),
+            inode,
         })
     }
 }
 
+/// The object `path` points to, if it is a symlink into the odb at `odb_root`.
+fn symlinked_object(
+    odb_root: Option<&Path>,
+    path: &Utf8Path,
+) -> Option<Result<Collected, BuildError>> {
+    let oid = path_to_oid(odb_root?, &fs::canonicalize(path).ok()?)?;
+    let file_info = fs::metadata(path).and_then(|meta| FileInfo::from_metadata(path, &meta));
+    Some(
+        file_info
+            .map(|file_info| Collected::Cached(file_info, oid))
+            .map_err(BuildError::Io),
+    )
+}
+
+/// Whether `file_info` is a hardlink to the object `oid` in `odb`.
+fn is_hardlink_to(odb: &Odb, file_info: &FileInfo, oid: &Oid) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(oid_to_path(&odb.path, oid))
+            .is_ok_and(|meta| file_info.inode == Some((meta.dev(), meta.ino())))
+    }
+    #[cfg(not(unix))]
+    {
+        // `inode` is only recorded on unix
+        let _ = (odb, file_info, oid);
+        false
+    }
+}
+
+type CollectedFiles = (Vec<FileInfo>, Vec<(FileInfo, Oid)>, BuildReport);
+
+/// Walks `root`, returning the files to hash, the symlinks into `odb` with
+/// the objects they point to, and the paths left out.
 fn collect_files(
     root: &Utf8Path,
     ignore: &Gitignore,
     jobs: usize,
-) -> Result<Vec<FileInfo>, BuildError> {
-    WalkDir::new(root)
-        .follow_links(true)
+    odb: &Odb,
+    symlinks: SymlinkPolicy,
+) -> CollectedFiles {
+    let odb_root = fs::canonicalize(&odb.path).ok();
+    let collected = WalkDir::new(root)
+        .follow_links(symlinks == SymlinkPolicy::Follow)
         .skip_hidden(false)
         .parallelism(Parallelism::RayonNewPool(jobs))
         .process_read_dir(|_, _, (), children| {
@@ -109,35 +242,88 @@ fn collect_files(
         })
         .into_iter()
         .par_bridge()
-        .map(|dir_entry_res| {
-            let dentry = dir_entry_res?;
-            if !dentry.file_type().is_file() {
-                return Ok(None);
+        .filter_map(|dir_entry_res| {
+            let dentry = match dir_entry_res {
+                Ok(dentry) => dentry,
+                Err(e) => {
+                    let path = e.path().map_or_else(|| root.into(), PathBuf::from);
+                    return Some(Collected::Error(path, BuildError::WalkError(e)));
+                }
+            };
+            let file_type = dentry.file_type();
+            let is_symlink = dentry.path_is_symlink();
+            if !file_type.is_file() && !is_symlink {
+                return None;
+            }
+            let path = dentry.path();
+            if ignore
+                .matched_path_or_any_parents(&path, file_type.is_dir())
+                .is_ignore()
+            {
+                return None;
             }
-            let path = &Utf8PathBuf::try_from(dentry.path())?;
-            if ignore.matched_path_or_any_parents(path, false).is_ignore() {
-                return Ok(None);
+            let path = match Utf8PathBuf::try_from(path) {
+                Ok(path) => path,
+                Err(e) => return Some(Collected::NonUtf8(e.into_path_buf())),
+            };
+            if is_symlink {
+                if let Some(cached) = symlinked_object(odb_root.as_deref(), &path) {
+                    return Some(cached.unwrap_or_else(|e| Collected::Error(path.into(), e)));
+                }
+                match symlinks {
+                    SymlinkPolicy::Follow => {}
+                    SymlinkPolicy::Skip => {
+                        debug!("skipping symlink {path}");
+                        return None;
+                    }
+                    SymlinkPolicy::Error => {
+                        let e = BuildError::Symlink(path.clone().into());
+                        return Some(Collected::Error(path.into(), e));
+                    }
+                }
             }
-            match dentry.metadata() {
-                Err(e) => Err(BuildError::WalkError(e)),
-                Ok(meta) => FileInfo::from_metadata(path, &meta)
-                    .map(Some)
-                    .map_err(BuildError::Io),
+            if !file_type.is_file() {
+                return None;
             }
+            let collected = match dentry.metadata() {
+                Err(e) => Collected::Error(path.into(), BuildError::WalkError(e)),
+                Ok(meta) => match FileInfo::from_metadata(&path, &meta) {
+                    Ok(file_info) => Collected::File(file_info),
+                    Err(e) => Collected::Error(path.into(), BuildError::Io(e)),
+                },
+            };
+            Some(collected)
         })
-        .filter_map(std::result::Result::transpose)
-        .collect()
+        .collect::<Vec<_>>();
+
+    let mut files = Vec::with_capacity(collected.len());
+    let mut cached = Vec::new();
+    let mut report = BuildReport::default();
+    for item in collected {
+        match item {
+            Collected::File(file_info) => files.push(file_info),
+            Collected::Cached(file_info, oid) => cached.push((file_info, oid)),
+            Collected::NonUtf8(path) => report.non_utf8.push(path),
+            Collected::Error(path, e) => report.errors.push((path, e)),
+        }
+    }
+    report.non_utf8.sort_unstable();
+    (files, cached, report)
 }
 
 #[derive(Default)]
 struct HashResults {
     new: Vec<FileInfo>,
     cached: Vec<(FileInfo, Oid)>,
+    /// Files with other hardlinks whose recorded hash is out of date, with
+    /// that hash, as they may have been replaced by a link to its object.
+    relinked: Vec<(FileInfo, Oid)>,
 }
 
 fn get_hashes(file_infos: Vec<FileInfo>, state: Option<&State>) -> Result<HashResults, BuildError> {
     let mut new = Vec::new();
     let mut cached = Vec::new();
+    let mut relinked = Vec::new();
     match state {
         Some(s) if !s.is_empty()? => {
             let keys: Vec<String> = file_infos
@@ -151,14 +337,22 @@ fn get_hashes(file_infos: Vec<FileInfo>, state: Option<&State>) -> Result<HashRe
                     Some(v) if v.checksum == file_info.checksum => {
                         cached.push((file_info, v.hash_info.oid));
                     }
+                    Some(v) if file_info.inode.is_some() => {
+                        relinked.push((file_info, v.hash_info.oid));
+                    }
                     _ => new.push(file_info),
                 }
             }
-            Ok(HashResults { new, cached })
+            Ok(HashResults {
+                new,
+                cached,
+                relinked,
+            })
         }
         _ => Ok(HashResults {
             new: file_infos,
             cached,
+            relinked,
         }),
     }
 }
@@ -175,7 +369,7 @@ fn set_hashes<'a>(
                 file.as_str().to_string(),
                 StateValue {
                     checksum: checksum.clone(),
-                    hash_info: StateHash { oid: oid.clone() },
+                    hash_info: StateHash { oid: *oid },
                     size: file_info.size,
                 },
             )
@@ -185,31 +379,108 @@ fn set_hashes<'a>(
     Ok(())
 }
 
-fn hash_files(file_infos: Vec<FileInfo>) -> std::io::Result<Vec<(FileInfo, Oid)>> {
-    if file_infos.is_empty() {
-        Ok(Vec::new())
-    } else {
-        file_infos
-            .into_par_iter()
-            .map(|file_info| {
-                let oid = file_md5(&file_info.path)?;
-                Ok((file_info, oid))
-            })
-            .collect()
-    }
+type HashErrors = Vec<(PathBuf, BuildError)>;
+
+fn hash_files(file_infos: Vec<FileInfo>) -> (Vec<(FileInfo, Oid)>, HashErrors) {
+    file_infos
+        .into_par_iter()
+        .partition_map(|file_info| match file_md5(&file_info.path) {
+            Ok(oid) => Either::Left((file_info, oid)),
+            Err(e) => Either::Right((file_info.path.into(), BuildError::Io(e))),
+        })
 }
 
+/// Hash `files`, saving every hash that succeeded to `state` even if others
+/// failed, so that a retry does not have to hash them again. Files that are
+/// hardlinks to the objects in `odb` recorded for them are not hashed.
 fn get_or_hash_files(
     files: Vec<FileInfo>,
     state: Option<&State>,
-) -> Result<Vec<(FileInfo, Oid)>, BuildError> {
-    let HashResults { new, mut cached } = log_durations("checking cache for hashed files", || {
+    odb: Option<&Odb>,
+) -> Result<(Vec<(FileInfo, Oid)>, HashErrors), BuildError> {
+    let HashResults {
+        mut new,
+        mut cached,
+        relinked,
+    } = log_durations("checking cache for hashed files", || {
         get_hashes(files, state)
     })?;
-    let new_entries = log_durations("hashing files", || hash_files(new))?;
+    let mut linked = Vec::new();
+    for (file_info, oid) in relinked {
+        if odb.is_some_and(|odb| is_hardlink_to(odb, &file_info, &oid)) {
+            linked.push((file_info, oid));
+        } else {
+            new.push(file_info);
+        }
+    }
+    let (mut new_entries, errors) = log_durations("hashing files", || hash_files(new));
+    new_entries.extend(linked);
     log_durations("saving hashes", || set_hashes(new_entries.iter(), state))?;
     cached.extend(new_entries);
-    Ok(cached)
+    Ok((cached, errors))
+}
+
+/// Fails with the error of the first path in `errors` unless the build goes
+/// on past them.
+fn check_errors(errors: &mut HashErrors, options: &BuildOptions) -> Result<(), BuildError> {
+    if options.continue_on_error {
+        return Ok(());
+    }
+    match errors.iter().position_min_by(|(a, _), (b, _)| a.cmp(b)) {
+        Some(first) => Err(errors.swap_remove(first).1),
+        None => Ok(()),
+    }
+}
+
+/// Hash the given files, reusing and updating the hashes recorded in `state`.
+pub(crate) fn hash_paths(
+    paths: Vec<Utf8PathBuf>,
+    state: Option<&State>,
+) -> Result<Vec<(Utf8PathBuf, Oid)>, BuildError> {
+    let files = paths
+        .into_par_iter()
+        .map(|path| FileInfo::from_metadata(&path, &fs::metadata(&path)?))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let (entries, mut errors) = get_or_hash_files(files, state, None)?;
+    check_errors(&mut errors, &BuildOptions::default())?;
+    Ok(entries
+        .into_iter()
+        .map(|(file_info, oid)| (file_info.path, oid))
+        .collect())
+}
+
+/// The hashes recorded in `state` for those of `paths` that exist and have
+/// not changed since, without hashing anything.
+pub(crate) fn cached_hashes(
+    paths: Vec<Utf8PathBuf>,
+    state: &State,
+) -> Result<HashMap<Utf8PathBuf, Oid>, BuildError> {
+    let files = paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let meta = fs::metadata(&path).ok().filter(fs::Metadata::is_file)?;
+            Some(FileInfo::from_metadata(&path, &meta))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let HashResults { cached, .. } = get_hashes(files, Some(state))?;
+    Ok(cached
+        .into_iter()
+        .map(|(file_info, oid)| (file_info.path, oid))
+        .collect())
+}
+
+/// Records that the files at `paths` hash to the given oids, e.g. as they
+/// were just checked out, so that they are not hashed again.
+pub(crate) fn record_hashes(
+    paths: Vec<(Utf8PathBuf, Oid)>,
+    state: &State,
+) -> Result<(), BuildError> {
+    let entries = paths
+        .into_par_iter()
+        .map(|(path, oid)| Ok((FileInfo::from_metadata(&path, &fs::metadata(&path)?)?, oid)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    set_hashes(entries.iter(), Some(state))?;
+    Ok(())
 }
 
 fn build_file(root: &Utf8Path, state: Option<&State>) -> Result<(Object, u64), BuildError> {
@@ -226,7 +497,7 @@ fn build_file(root: &Utf8Path, state: Option<&State>) -> Result<(Object, u64), B
         if let Some(s) = state {
             let sv = StateValue {
                 checksum: file_info.checksum.clone(),
-                hash_info: StateHash { oid: oid.clone() },
+                hash_info: StateHash { oid },
                 size: file_info.size,
             };
             s.set(key, &sv)?;
@@ -236,47 +507,63 @@ fn build_file(root: &Utf8Path, state: Option<&State>) -> Result<(Object, u64), B
     Ok((Object::HashFile(oid), file_info.size))
 }
 
+/// Builds the tree of files under `root`, and returns it with the paths of
+/// the files whose relpath was normalized, keyed by that relpath.
 fn build_tree_from_entries(
     root: &Utf8Path,
     file_infos_with_oids: impl Iterator<Item = (FileInfo, Oid)>,
-) -> Result<Tree, BuildError> {
-    let mut entries = file_infos_with_oids
+    normalization: Normalization,
+) -> Result<(Tree, Sources), BuildError> {
+    let mut sources = Sources::new();
+    let entries = file_infos_with_oids
         .map(|(file_info, oid)| {
-            let relpath = file_info.path.strip_prefix(root)?.to_path_buf();
+            let relpath = file_info.path.strip_prefix(root)?;
+            let relpath = match normalization.relpath(relpath) {
+                Cow::Borrowed(relpath) => relpath.to_path_buf(),
+                Cow::Owned(relpath) => {
+                    sources.insert(relpath.clone(), file_info.path.clone());
+                    relpath
+                }
+            };
             Ok(TreeEntry { relpath, oid })
         })
         .collect::<Result<Vec<_>, BuildError>>()?;
-    entries.par_sort_unstable(); // sort keys
-    Ok(Tree { entries })
+    Ok((Tree::from_entries(entries)?, sources))
 }
 
 fn build_tree(
+    odb: &Odb,
     root: &Utf8Path,
     state: Option<&State>,
     ignore: &Gitignore,
     jobs: usize,
-) -> Result<(Object, u64), BuildError> {
-    let result = log_durations("collecting files", || collect_files(root, ignore, jobs));
-    match result {
-        Ok(files) => {
-            let size = files.iter().map(|fi| fi.size).sum();
-            let all_entries = get_or_hash_files(files, state)?;
-            let tree = log_durations("building tree", || {
-                build_tree_from_entries(root, all_entries.into_iter())
-            })?;
-            Ok((Object::Tree(tree), size))
-        }
-        Err(e) => Err(e),
-    }
+    options: &BuildOptions,
+) -> Result<(Object, u64, BuildReport), BuildError> {
+    let (files, cached, mut report) = log_durations("collecting files", || {
+        collect_files(root, ignore, jobs, odb, options.symlinks)
+    });
+    check_errors(&mut report.errors, options)?;
+    let (mut all_entries, mut errors) = get_or_hash_files(files, state, Some(odb))?;
+    check_errors(&mut errors, options)?;
+    all_entries.extend(cached);
+    let size = all_entries.iter().map(|(fi, _)| fi.size).sum();
+    report.errors.extend(errors);
+    report.errors.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let (tree, sources) = log_durations("building tree", || {
+        build_tree_from_entries(root, all_entries.into_iter(), odb.normalization)
+    })?;
+    report.sources = sources;
+    Ok((Object::Tree(tree), size, report))
 }
 
 pub fn build(
-    _odb: &Odb,
+    odb: &Odb,
     root: &Utf8Path,
     state: Option<&State>,
     ignore: &Gitignore,
     jobs: usize,
-) -> Result<(Object, u64), BuildError> {
+    options: &BuildOptions,
+) -> Result<(Object, u64, BuildReport), BuildError> {
     let root = camino::absolute_utf8(root)?;
     assert!(
         !ignore
@@ -286,8 +573,9 @@ pub fn build(
     );
 
     if root.is_file() {
-        build_file(&root, state)
+        let (obj, size) = build_file(&root, state)?;
+        Ok((obj, size, BuildReport::default()))
     } else {
-        build_tree(&root, state, ignore, jobs)
+        build_tree(odb, &root, state, ignore, jobs, options)
     }
 }
@@ -1,10 +1,15 @@
-use crate::fsutils::transfer_file;
-use crate::models::{DvcFile, Output};
-use crate::objects::{Tree, TreeError};
+use crate::build::{BuildError, cached_hashes, hash_paths, record_hashes};
+use crate::fsutils::{remove_path, transfer_file};
+use crate::index::{IndexError, collect_outputs};
+use crate::objects::{Oid, Tree, TreeError};
 use crate::odb::{Odb, oid_to_path};
-use camino::Utf8PathBuf;
+use crate::state::State;
+use camino::{Utf8Path, Utf8PathBuf};
 use indicatif::{ParallelProgressIterator, ProgressBar};
+use itertools::Itertools;
+use log::warn;
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Error as IOError;
 use std::path::Path;
@@ -17,7 +22,31 @@ pub enum CheckoutError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
-    SerdeYaml(#[from] serde_yaml::Error),
+    IndexError(#[from] IndexError),
+    #[error(transparent)]
+    BuildError(#[from] BuildError),
+    #[error(transparent)]
+    FromPathBufError(#[from] camino::FromPathBufError),
+    #[error("object {0} for '{1}' is missing from cache")]
+    MissingObject(Oid, Utf8PathBuf),
+    #[error("object {0} for '{1}' is corrupted in cache")]
+    CorruptedObject(Oid, Utf8PathBuf),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CheckoutOptions {
+    /// Skip entries whose objects are not in the cache instead of failing.
+    pub allow_missing: bool,
+    /// Re-hash cache objects before placing them and refuse corrupted ones.
+    pub verify: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct CheckoutResult {
+    /// Paths that could not be checked out, with the oid missing from cache.
+    pub missing: Vec<(Utf8PathBuf, Oid)>,
+    /// Paths skipped because their cache object failed verification.
+    pub corrupted: Vec<(Utf8PathBuf, Oid)>,
 }
 
 fn checkout_file(from: &Path, to: &Path, cache_types: Option<&Vec<String>>) -> std::io::Result<()> {
@@ -61,42 +90,427 @@ fn checkout_file(from: &Path, to: &Path, cache_types: Option<&Vec<String>>) -> s
     Err(IOError::other("No cache type worked"))
 }
 
+enum Skipped {
+    Missing,
+    Corrupted,
+}
+
+/// What happened to a tree entry in `checkout_tree`.
+enum Placed {
+    Staged(Utf8PathBuf, Oid),
+    Unchanged,
+    Skipped(Skipped, Utf8PathBuf, Oid),
+}
+
+impl Skipped {
+    fn into_error(self, oid: Oid, path: Utf8PathBuf) -> CheckoutError {
+        match self {
+            Self::Missing => CheckoutError::MissingObject(oid, path),
+            Self::Corrupted => CheckoutError::CorruptedObject(oid, path),
+        }
+    }
+}
+
+impl CheckoutResult {
+    #[must_use]
+    pub fn merge(mut self, other: Self) -> Self {
+        self.missing.extend(other.missing);
+        self.corrupted.extend(other.corrupted);
+        self
+    }
+
+    fn from_skipped(skipped: Vec<(Skipped, Utf8PathBuf, Oid)>) -> Self {
+        let mut result = Self::default();
+        for (reason, path, oid) in skipped {
+            match reason {
+                Skipped::Missing => result.missing.push((path, oid)),
+                Skipped::Corrupted => result.corrupted.push((path, oid)),
+            }
+        }
+        result
+    }
+}
+
+/// Returns the subset of `oids` whose cache objects don't hash to their oid.
+fn find_corrupted<'a>(
+    odb: &Odb,
+    oids: impl Iterator<Item = &'a Oid>,
+    state: Option<&State>,
+) -> Result<HashSet<Oid>, CheckoutError> {
+    let expected = oids
+        .map(|oid| {
+            let path = Utf8PathBuf::try_from(oid_to_path(&odb.path, oid))?;
+            Ok((path, oid))
+        })
+        .collect::<Result<HashMap<_, _>, CheckoutError>>()?;
+    let hashes = hash_paths(expected.keys().cloned().collect(), state)?;
+    Ok(hashes
+        .into_iter()
+        .filter_map(|(path, md5)| {
+            let oid = expected[&path];
+            (oid.digest() != md5.digest()).then_some(*oid)
+        })
+        .collect())
+}
+
+/// Whether `dst` already holds the object at `src`: a link to it, or a file
+/// whose hash recorded in the state is `oid`.
+fn is_checked_out(src: &Path, dst: &Utf8Path, oid: &Oid, cached: Option<&Oid>) -> bool {
+    let Ok(meta) = fs::symlink_metadata(dst) else {
+        return false;
+    };
+    if meta.is_symlink() {
+        return fs::read_link(dst).is_ok_and(|target| target == src);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if fs::metadata(src).is_ok_and(|src| (src.dev(), src.ino()) == (meta.dev(), meta.ino())) {
+            return true;
+        }
+    }
+    cached == Some(oid)
+}
+
+/// Stages the entries of the tree at `from` that differ from `to`, and
+/// returns their relpaths and oids. Files in `to` are not touched.
+fn checkout_tree(
+    odb: &Odb,
+    from: &Path,
+    staging: &Utf8Path,
+    to: &Utf8Path,
+    cache_types: Option<&Vec<String>>,
+    state: Option<&State>,
+    options: &CheckoutOptions,
+) -> Result<(CheckoutResult, Vec<(Utf8PathBuf, Oid)>), CheckoutError> {
+    let tree = Tree::load_from(&from.to_path_buf())?.normalize(odb.normalization)?;
+    for relpaths in tree.case_collisions() {
+        warn!(
+            "{} differ only in case and overwrite each other on case-insensitive filesystems",
+            relpaths.iter().join(", ")
+        );
+    }
+    let corrupted = if options.verify {
+        let present: Vec<_> = tree
+            .iter()
+            .map(|entry| entry.oid)
+            .filter(|oid| oid_to_path(&odb.path, oid).is_file())
+            .collect();
+        find_corrupted(odb, present.iter(), state)?
+    } else {
+        HashSet::new()
+    };
+    let cached = match state {
+        Some(state) => {
+            let to = camino::absolute_utf8(to)?;
+            cached_hashes(
+                tree.iter().map(|entry| to.join(entry.relpath)).collect(),
+                state,
+            )?
+            .into_iter()
+            .filter_map(|(path, oid)| Some((path.strip_prefix(&to).ok()?.to_path_buf(), oid)))
+            .collect()
+        }
+        None => HashMap::new(),
+    };
+    let pb = ProgressBar::new(tree.len() as u64);
+
+    fs::create_dir_all(staging)?;
+    let placed = tree
+        .par_iter()
+        .progress_with(pb)
+        .map(|entry| {
+            let src = oid_to_path(&odb.path, &entry.oid);
+            let dst = to.join(&entry.relpath);
+            let reason = if !src.is_file() {
+                Skipped::Missing
+            } else if corrupted.contains(&entry.oid) {
+                Skipped::Corrupted
+            } else if is_checked_out(&src, &dst, &entry.oid, cached.get(&entry.relpath)) {
+                return Ok(Placed::Unchanged);
+            } else {
+                let staged = staging.join(&entry.relpath);
+                checkout_file(&src, staged.as_std_path(), cache_types)?;
+                return Ok(Placed::Staged(entry.relpath, entry.oid));
+            };
+            if options.allow_missing {
+                Ok(Placed::Skipped(reason, dst, entry.oid))
+            } else {
+                Err(reason.into_error(entry.oid, dst))
+            }
+        })
+        .collect::<Result<Vec<_>, CheckoutError>>()?;
+
+    let mut staged = Vec::new();
+    let mut skipped = Vec::new();
+    for placed in placed {
+        match placed {
+            Placed::Staged(relpath, oid) => staged.push((relpath, oid)),
+            Placed::Unchanged => {}
+            Placed::Skipped(reason, dst, oid) => skipped.push((reason, dst, oid)),
+        }
+    }
+    Ok((CheckoutResult::from_skipped(skipped), staged))
+}
+
+/// Path next to `path` used to stage or back up a checkout, e.g. `.data.staging`.
+fn sibling_path(path: &Utf8Path, suffix: &str) -> std::io::Result<Utf8PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        IOError::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("cannot checkout to '{path}'"),
+        )
+    })?;
+    Ok(path.with_file_name(format!(".{name}.{suffix}")))
+}
+
+/// Clean up after a checkout into `to` that was interrupted, e.g. by Ctrl-C.
+///
+/// An interrupted run leaves either a stale staging path, which is discarded,
+/// a backup of the old contents that has not been swapped back yet, or the
+/// files a tree checkout had replaced so far, which are put back.
+fn recover_interrupted(to: &Utf8Path) -> std::io::Result<()> {
+    let backup = sibling_path(to, "backup")?;
+    if fs::symlink_metadata(&backup).is_ok() {
+        if fs::symlink_metadata(to).is_ok() {
+            remove_path(&backup)?;
+        } else {
+            fs::rename(&backup, to)?;
+        }
+    }
+    let replaced = sibling_path(to, "replaced")?;
+    if fs::symlink_metadata(&replaced).is_ok() {
+        fs::create_dir_all(to)?;
+        restore_replaced(replaced.as_std_path(), to.as_std_path())?;
+        remove_path(&replaced)?;
+    }
+    Ok(())
+}
+
+/// Replace `to` with `staging`, restoring the old contents if that fails.
+fn swap_into_place(staging: &Utf8Path, to: &Utf8Path) -> std::io::Result<()> {
+    let backup = sibling_path(to, "backup")?;
+    let has_old = fs::symlink_metadata(to).is_ok();
+    if has_old {
+        fs::rename(to, &backup)?;
+    }
+    if let Err(e) = fs::rename(staging, to) {
+        if has_old {
+            fs::rename(&backup, to)?;
+        }
+        return Err(e);
+    }
+    if has_old {
+        remove_path(&backup)?;
+    }
+    Ok(())
+}
+
+/// Moves the path at `relpath` in `to` to the same relpath in `replaced`.
+fn move_aside(to: &Utf8Path, replaced: &Utf8Path, relpath: &Utf8Path) -> std::io::Result<()> {
+    let backup = replaced.join(relpath);
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(to.join(relpath), backup)
+}
+
+/// Moves everything in `replaced` back to the same place in `to`, replacing
+/// whatever was put there since.
+fn restore_replaced(replaced: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(replaced)? {
+        let entry = entry?;
+        let (src, dst) = (entry.path(), to.join(entry.file_name()));
+        if entry.file_type()?.is_dir() && fs::symlink_metadata(&dst).is_ok_and(|meta| meta.is_dir())
+        {
+            // only a parent of the replaced paths, which stayed a directory
+            restore_replaced(&src, &dst)?;
+        } else {
+            remove_path(&dst)?;
+            fs::rename(&src, &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the `staged` relpaths from `staging` into the directory `to`,
+/// moving whatever is at their paths, or is a file where they need a
+/// directory, into `replaced` first.
+fn replace_staged(
+    staging: &Utf8Path,
+    to: &Utf8Path,
+    replaced: &Utf8Path,
+    staged: &[(Utf8PathBuf, Oid)],
+) -> std::io::Result<()> {
+    let mut parents = HashSet::new();
+    for (relpath, _) in staged {
+        for parent in relpath.ancestors().skip(1) {
+            if parent.as_str().is_empty() || !parents.insert(parent) {
+                break;
+            }
+            if fs::symlink_metadata(to.join(parent)).is_ok_and(|meta| !meta.is_dir()) {
+                move_aside(to, replaced, parent)?;
+            }
+        }
+    }
+    staged.par_iter().try_for_each(|(relpath, _)| {
+        let dst = to.join(relpath);
+        if fs::symlink_metadata(&dst).is_ok() {
+            move_aside(to, replaced, relpath)?;
+        }
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staging.join(relpath), &dst)
+    })
+}
+
+/// Moves the `staged` relpaths from `staging` into the directory `to`, and
+/// leaves every other file in `to` alone. If that fails partway, the files
+/// placed so far are removed and the ones they replaced are restored.
+fn place_staged(
+    staging: &Utf8Path,
+    to: &Utf8Path,
+    staged: &[(Utf8PathBuf, Oid)],
+) -> std::io::Result<()> {
+    if !fs::metadata(to).is_ok_and(|meta| meta.is_dir()) {
+        return swap_into_place(staging, to);
+    }
+    let replaced = sibling_path(to, "replaced")?;
+    if let Err(e) = replace_staged(staging, to, &replaced, staged) {
+        for (relpath, _) in staged {
+            // staged files that are gone from `staging` were placed
+            if fs::symlink_metadata(staging.join(relpath)).is_err() {
+                remove_path(to.join(relpath))?;
+            }
+        }
+        if fs::symlink_metadata(&replaced).is_ok() {
+            restore_replaced(replaced.as_std_path(), to.as_std_path())?;
+        }
+        remove_path(&replaced)?;
+        return Err(e);
+    }
+    remove_path(&replaced)?;
+    remove_path(staging)
+}
+
+/// Checks out `oid` to `to`.
+///
+/// The object is first materialized into a sibling staging path, so a failed
+/// checkout leaves the previous contents in place. A file then replaces `to`,
+/// while the staged files of a tree are moved into `to` one by one, keeping
+/// the files they replace until all of them are in place: files that are
+/// already checked out, skipped entries and untracked files stay.
 pub fn checkout_obj(
     odb: &Odb,
-    oid: &str,
+    oid: &Oid,
     to: &Utf8PathBuf,
     cache_types: &Option<Vec<String>>,
-) -> Result<(), CheckoutError> {
+    state: Option<&State>,
+    options: &CheckoutOptions,
+) -> Result<CheckoutResult, CheckoutError> {
     let from = oid_to_path(&odb.path, oid);
-    if oid.ends_with(".dir") {
-        let tree = Tree::load_from(&from)?;
-        let pb = ProgressBar::new(tree.entries.len() as u64);
+    let oid = *oid;
+    let reason = if !from.is_file() {
+        Some(Skipped::Missing)
+    } else if options.verify && !find_corrupted(odb, std::iter::once(&oid), state)?.is_empty() {
+        Some(Skipped::Corrupted)
+    } else {
+        None
+    };
+    if let Some(reason) = reason {
+        return if options.allow_missing {
+            Ok(CheckoutResult::from_skipped(vec![(
+                reason,
+                to.clone(),
+                oid,
+            )]))
+        } else {
+            Err(reason.into_error(oid, to.clone()))
+        };
+    }
 
-        fs::create_dir_all(to)?;
-        tree.entries
-            .par_iter()
-            .progress_with(pb)
-            .try_for_each(|entry| {
-                let src = oid_to_path(&odb.path, &entry.oid);
-                let dst = to.join(&entry.relpath);
-                checkout_file(&src, &dst.into_std_path_buf(), cache_types.as_ref())
-            })?;
-        return Ok(());
-    }
-    Ok(checkout_file(
-        &from,
-        &to.clone().into_std_path_buf(),
-        cache_types.as_ref(),
-    )?)
+    recover_interrupted(to)?;
+    let staging = sibling_path(to, "staging")?;
+    remove_path(&staging)?;
+
+    let result = if oid.is_tree() {
+        checkout_tree(
+            odb,
+            &from,
+            &staging,
+            to,
+            cache_types.as_ref(),
+            state,
+            options,
+        )
+        .and_then(|(result, staged)| {
+            place_staged(&staging, to, &staged)?;
+            let to = camino::absolute_utf8(to)?;
+            let placed = staged
+                .into_iter()
+                .map(|(relpath, oid)| (to.join(relpath), oid));
+            Ok((result, placed.collect()))
+        })
+    } else {
+        checkout_file(&from, staging.as_std_path(), cache_types.as_ref())
+            .and_then(|()| swap_into_place(&staging, to))
+            .and_then(|()| camino::absolute_utf8(to))
+            .map(|to| (CheckoutResult::default(), vec![(to, oid)]))
+            .map_err(CheckoutError::from)
+    };
+    match result {
+        Ok((result, placed)) => {
+            // so that the files are not hashed again, e.g. by `status`
+            if let Some(state) = state {
+                record_hashes(placed, state)?;
+            }
+            Ok(result)
+        }
+        Err(e) => {
+            let _ = remove_path(&staging);
+            Err(e)
+        }
+    }
 }
 
 pub fn checkout(
     odb: &Odb,
     dvcfile_path: &Utf8PathBuf,
     cache_types: &Option<Vec<String>>,
-) -> Result<(), CheckoutError> {
-    let contents = &fs::read_to_string(dvcfile_path)?;
-    let dvcfile_obj: DvcFile = serde_yaml::from_str(contents)?;
-    let Output { oid, path, .. } = dvcfile_obj.outs.0;
-    checkout_obj(odb, &oid, &path, cache_types)
+    state: Option<&State>,
+    options: &CheckoutOptions,
+) -> Result<CheckoutResult, CheckoutError> {
+    checkout_all(
+        odb,
+        std::slice::from_ref(dvcfile_path),
+        cache_types,
+        state,
+        options,
+    )
+}
+
+/// Checks out every output recorded in `dvcfiles` concurrently on the global
+/// thread pool.
+pub fn checkout_all(
+    odb: &Odb,
+    dvcfiles: &[Utf8PathBuf],
+    cache_types: &Option<Vec<String>>,
+    state: Option<&State>,
+    options: &CheckoutOptions,
+) -> Result<CheckoutResult, CheckoutError> {
+    let outputs = collect_outputs(dvcfiles)?;
+    outputs
+        .par_iter()
+        .map(|out| {
+            checkout_obj(
+                odb,
+                &out.output.oid,
+                &out.path(),
+                cache_types,
+                state,
+                options,
+            )
+        })
+        .try_reduce(CheckoutResult::default, |a, b| Ok(a.merge(b)))
 }
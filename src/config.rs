@@ -1,11 +1,13 @@
+use crate::objects::Normalization;
 use config::FileFormat;
-use config::{Config as Conf, File};
+use config::{Config as Conf, Environment, File};
 use directories::ProjectDirs;
 use serde::{Deserialize, de};
 use serde_json::Value;
 use serde_with::StringWithSeparator;
 use serde_with::formats::CommaSeparator;
 use serde_with::serde_as;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use thiserror::Error as ThisError;
 
@@ -27,6 +29,8 @@ pub struct Core {
     pub site_cache_dir: Option<PathBuf>,
     #[serde(default)]
     pub checksum_jobs: Option<usize>,
+    #[serde(default)]
+    pub remote: Option<String>,
 }
 
 #[serde_as]
@@ -39,12 +43,47 @@ pub struct Cache {
     pub typ: Option<Vec<String>>,
 }
 
+/// Name of the config file in `.dvc` for options that DVC does not know
+/// about, as DVC rejects unknown sections in its own config files.
+pub const DVC_DATA_CONFIG: &str = "dvc-data.ini";
+
+/// The `[tree]` section of `DVC_DATA_CONFIG`, for trees shared between
+/// platforms. Can be overridden with e.g. `DVC_DATA_TREE__NORMALIZE_UNICODE`.
+#[derive(Debug, Deserialize, Default)]
+pub struct TreeConfig {
+    /// Read `\` in relpaths as a separator.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub normalize_backslashes: bool,
+    /// Compose relpaths to Unicode NFC.
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    pub normalize_unicode: bool,
+}
+
+impl TreeConfig {
+    pub fn normalization(&self) -> Normalization {
+        Normalization {
+            backslashes: self.normalize_backslashes,
+            nfc: self.normalize_unicode,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub core: Core,
     #[serde(default)]
     pub cache: Cache,
+    /// Read from `DVC_DATA_CONFIG` and the environment instead.
+    #[serde(skip)]
+    pub tree: TreeConfig,
+    #[serde(flatten, deserialize_with = "deserialize_remotes")]
+    pub remotes: HashMap<String, Remote>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Remote {
+    pub url: String,
 }
 
 fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
@@ -64,6 +103,24 @@ where
     }
 }
 
+/// Collects `['remote "name"']` sections, keyed by the remote name.
+fn deserialize_remotes<'de, D>(deserializer: D) -> Result<HashMap<String, Remote>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let sections = HashMap::<String, Value>::deserialize(deserializer)?;
+    let mut remotes = HashMap::new();
+    for (section, value) in sections {
+        let section = section.trim_matches('\'');
+        let Some(name) = section.strip_prefix("remote ") else {
+            continue;
+        };
+        let remote = Remote::deserialize(value).map_err(de::Error::custom)?;
+        remotes.insert(name.trim_matches('"').to_owned(), remote);
+    }
+    Ok(remotes)
+}
+
 impl Config {
     pub fn new(control_dir: &Path) -> Result<Self, ConfigError> {
         let conf = Conf::builder()
@@ -89,6 +146,32 @@ impl Config {
             )
             .build()?
             .try_deserialize()?;
-        Ok(conf)
+        Ok(Self {
+            tree: Self::tree_config(control_dir)?,
+            ..conf
+        })
+    }
+
+    fn tree_config(control_dir: &Path) -> Result<TreeConfig, ConfigError> {
+        #[derive(Deserialize)]
+        struct DvcDataConfig {
+            #[serde(default)]
+            tree: TreeConfig,
+        }
+
+        let conf: DvcDataConfig = Conf::builder()
+            .add_source(
+                File::from(control_dir.join(DVC_DATA_CONFIG))
+                    .required(false)
+                    .format(FileFormat::Ini),
+            )
+            .add_source(
+                Environment::with_prefix("DVC_DATA")
+                    .prefix_separator("_")
+                    .separator("__"),
+            )
+            .build()?
+            .try_deserialize()?;
+        Ok(conf.tree)
     }
 }
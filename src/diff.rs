@@ -1,8 +1,13 @@
 use camino::{FromPathError, Utf8Path, Utf8PathBuf};
 
-use crate::objects::{Object, Tree, TreeError};
-use crate::odb::Odb;
-use std::collections::HashMap;
+use crate::index::TrackedOutput;
+use crate::objects::{Object, Oid, Tree, TreeError};
+use crate::odb::{Odb, oid_to_path};
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use thiserror::Error as ThisError;
 
 #[derive(Debug, ThisError)]
@@ -13,39 +18,282 @@ pub enum DiffError {
     TreeError(#[from] TreeError),
 }
 
+/// Default for `Diff::detect_renames`.
+pub const DEFAULT_RENAME_LIMIT: usize = 8;
+
+/// Changes between two objects, keyed and iterated in path order.
 #[derive(Default, Debug)]
 pub struct Diff {
-    pub added: HashMap<Utf8PathBuf, String>,
-    pub modified: HashMap<Utf8PathBuf, (String, String)>,
-    pub removed: HashMap<Utf8PathBuf, String>,
-    pub unchanged: HashMap<Utf8PathBuf, String>,
+    pub added: BTreeMap<Utf8PathBuf, Oid>,
+    pub modified: BTreeMap<Utf8PathBuf, (Oid, Oid)>,
+    pub removed: BTreeMap<Utf8PathBuf, Oid>,
+    /// Number of entries with the same oid on both sides.
+    pub unchanged: usize,
+    /// New path mapped to the old path and the oid they share.
+    pub renamed: BTreeMap<Utf8PathBuf, (Utf8PathBuf, Oid)>,
+    /// Sizes of changed entries, see `Diff::fill_sizes`.
+    pub sizes: BTreeMap<Utf8PathBuf, Sizes>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+/// Aggregated file changes under a directory, see `Diff::summary`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub added: u64,
+    pub modified: u64,
+    pub removed: u64,
+    pub renamed: u64,
+    pub bytes_added: u64,
+    pub bytes_removed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeType {
+    Added,
+    Modified,
+    Removed,
+    Renamed,
+}
+
+/// A single changed path in a `Diff`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Change<'a> {
+    pub path: &'a Utf8Path,
+    #[serde(rename = "type")]
+    pub typ: ChangeType,
+    pub old: Option<&'a Oid>,
+    pub new: Option<&'a Oid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<&'a Utf8Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
 }
 
 impl Diff {
+    /// Changed paths without the unchanged ones, grouped by directory: sorted
+    /// by parent directory, then by name, so the files directly in a
+    /// directory are listed together before those in its subdirectories.
+    pub fn changes(&self) -> Vec<Change<'_>> {
+        let added = self.added.iter().map(|(path, new)| Change {
+            path,
+            typ: ChangeType::Added,
+            old: None,
+            new: Some(new),
+            old_path: None,
+            old_size: None,
+            new_size: None,
+        });
+        let modified = self.modified.iter().map(|(path, (old, new))| Change {
+            path,
+            typ: ChangeType::Modified,
+            old: Some(old),
+            new: Some(new),
+            old_path: None,
+            old_size: None,
+            new_size: None,
+        });
+        let removed = self.removed.iter().map(|(path, old)| Change {
+            path,
+            typ: ChangeType::Removed,
+            old: Some(old),
+            new: None,
+            old_path: None,
+            old_size: None,
+            new_size: None,
+        });
+        let renamed = self.renamed.iter().map(|(path, (old_path, oid))| Change {
+            path,
+            typ: ChangeType::Renamed,
+            old: Some(oid),
+            new: Some(oid),
+            old_path: Some(old_path),
+            old_size: None,
+            new_size: None,
+        });
+        let mut changes: Vec<_> = added
+            .chain(modified)
+            .chain(removed)
+            .chain(renamed)
+            .map(|change| match self.sizes.get(change.path) {
+                Some(sizes) => Change {
+                    old_size: sizes.old,
+                    new_size: sizes.new,
+                    ..change
+                },
+                None => change,
+            })
+            .collect();
+        changes.sort_unstable_by_key(|change| {
+            (change.path.parent(), change.path.file_name(), change.typ)
+        });
+        changes
+    }
+
     #[must_use]
     pub fn merge(mut self, other: Self) -> Self {
         self.added.extend(other.added);
         self.modified.extend(other.modified);
         self.removed.extend(other.removed);
-        self.unchanged.extend(other.unchanged);
+        self.unchanged += other.unchanged;
+        self.renamed.extend(other.renamed);
+        self.sizes.extend(other.sizes);
         Self {
             added: self.added,
             modified: self.modified,
             removed: self.removed,
             unchanged: self.unchanged,
+            renamed: self.renamed,
+            sizes: self.sizes,
+        }
+    }
+
+    /// Records the sizes of changed files from their cache objects, falling
+    /// back to the workspace file at `workdir.join(path)` for new entries.
+    pub fn fill_sizes(&mut self, odb: &Odb, workdir: Option<&Utf8Path>) {
+        let object_size = |oid: &Oid| {
+            if oid.is_tree() {
+                return None;
+            }
+            fs::metadata(oid_to_path(&odb.path, oid))
+                .ok()
+                .map(|meta| meta.len())
+        };
+        let sizes: Vec<_> = self
+            .changes()
+            .into_iter()
+            .filter(|change| !change.old.or(change.new).is_some_and(Oid::is_tree))
+            .map(|change| {
+                let old = change.old.and_then(object_size);
+                let new = change.new.and_then(object_size).or_else(|| {
+                    fs::metadata(workdir?.join(change.path))
+                        .ok()
+                        .filter(fs::Metadata::is_file)
+                        .map(|meta| meta.len())
+                });
+                (change.path.to_path_buf(), Sizes { old, new })
+            })
+            .collect();
+        self.sizes.extend(sizes);
+    }
+
+    /// Aggregates changed files by the first `depth` components of their
+    /// parent directory. Directory objects themselves are not counted, and a
+    /// file renamed to another key counts as removed from the old one and
+    /// added to the new one.
+    pub fn summary(&self, depth: usize) -> BTreeMap<String, Summary> {
+        let key = |path: &Utf8Path| {
+            let components: Vec<_> = path.iter().collect();
+            if components.len() > depth {
+                components[..depth].join("/") + "/"
+            } else {
+                path.to_string()
+            }
+        };
+        let mut summary: BTreeMap<String, Summary> = BTreeMap::new();
+        for change in self.changes() {
+            if change.old.or(change.new).is_some_and(Oid::is_tree) {
+                continue;
+            }
+            let new_key = key(change.path);
+            if let Some(old_path) = change.old_path {
+                let old_key = key(old_path);
+                if old_key != new_key {
+                    let old_entry = summary.entry(old_key).or_default();
+                    old_entry.removed += 1;
+                    old_entry.bytes_removed += change.old_size.unwrap_or_default();
+                    let new_entry = summary.entry(new_key).or_default();
+                    new_entry.added += 1;
+                    new_entry.bytes_added += change.new_size.unwrap_or_default();
+                    continue;
+                }
+            }
+            let entry = summary.entry(new_key).or_default();
+            match change.typ {
+                ChangeType::Added => entry.added += 1,
+                ChangeType::Modified => entry.modified += 1,
+                ChangeType::Removed => entry.removed += 1,
+                ChangeType::Renamed => {
+                    entry.renamed += 1;
+                    continue;
+                }
+            }
+            entry.bytes_added += change.new_size.unwrap_or_default();
+            entry.bytes_removed += change.old_size.unwrap_or_default();
         }
+        summary
     }
 
     pub fn is_empty(&self) -> bool {
-        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+    }
+
+    /// Pairs removed and added paths sharing an oid into renames.
+    ///
+    /// If more than `limit` paths were removed or added with the same oid,
+    /// e.g. for many copies of an empty file, the pairing would be arbitrary
+    /// and those paths are left as they are. A `limit` of 0 disables this.
+    #[must_use]
+    pub fn detect_renames(mut self, limit: usize) -> Self {
+        let mut candidates: HashMap<Oid, (Vec<&Utf8PathBuf>, Vec<&Utf8PathBuf>)> = HashMap::new();
+        for (path, oid) in &self.removed {
+            candidates.entry(*oid).or_default().0.push(path);
+        }
+        for (path, oid) in &self.added {
+            if let Some((_, added)) = candidates.get_mut(oid) {
+                added.push(path);
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (mut removed, mut added) in candidates.into_values() {
+            if added.is_empty() || removed.len() > limit || added.len() > limit {
+                continue;
+            }
+            removed.sort_unstable();
+            added.sort_unstable();
+            pairs.extend(removed.into_iter().cloned().zip(added.into_iter().cloned()));
+        }
+        for (old_path, new_path) in pairs {
+            if let Some(oid) = self.removed.remove(&old_path) {
+                self.added.remove(&new_path);
+                self.renamed.insert(new_path, (old_path, oid));
+            }
+        }
+        self
+    }
+}
+
+/// Serializes as the list of `Diff::changes`.
+impl Serialize for Diff {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let changes = self.changes();
+        let mut seq = serializer.serialize_seq(Some(changes.len()))?;
+        for change in changes {
+            seq.serialize_element(&change)?;
+        }
+        seq.end()
     }
 }
 
 pub fn diff(
     odb: &Odb,
     root: &Utf8Path,
-    old: Option<&str>,
-    new: Option<&str>,
+    old: Option<&Oid>,
+    new: Option<&Oid>,
 ) -> Result<Diff, DiffError> {
     let mut diff = diff_root(root, old, new);
     let granular_diff = diff_oid(odb, old, new)?;
@@ -59,13 +307,76 @@ pub fn diff(
     for (path, key) in granular_diff.removed {
         diff.removed.insert(root.join(path), key);
     }
-    for (path, key) in granular_diff.unchanged {
-        diff.unchanged.insert(root.join(path), key);
+    diff.unchanged += granular_diff.unchanged;
+    for (path, (old_path, key)) in granular_diff.renamed {
+        diff.renamed
+            .insert(root.join(path), (root.join(old_path), key));
     }
     Ok(diff)
 }
 
-pub fn diff_oid(odb: &Odb, old: Option<&str>, new: Option<&str>) -> Result<Diff, DiffError> {
+/// Diffs two objects, reporting entries relative to `root`.
+pub fn diff_obj(root: &Utf8Path, old: Option<Object>, new: Option<Object>) -> Diff {
+    let mut diff = Diff::default();
+    let granular_diff = diff_object(old, new);
+
+    for (path, key) in granular_diff.added {
+        diff.added.insert(root.join(path), key);
+    }
+    for (path, (old_key, new_key)) in granular_diff.modified {
+        diff.modified.insert(root.join(path), (old_key, new_key));
+    }
+    for (path, key) in granular_diff.removed {
+        diff.removed.insert(root.join(path), key);
+    }
+    diff.unchanged += granular_diff.unchanged;
+    for (path, (old_path, key)) in granular_diff.renamed {
+        diff.renamed
+            .insert(root.join(path), (root.join(old_path), key));
+    }
+    diff
+}
+
+/// Tracked outputs keyed by their path, with their oid and loaded object, or
+/// `None` for a tree object that is not in the cache.
+pub type Outputs = HashMap<Utf8PathBuf, (Oid, Option<Object>)>;
+
+/// Loads the recorded objects of `outputs` from `odb`. Tree objects missing
+/// from the cache are left out, so only the output itself gets diffed.
+pub fn load_output_objects(odb: &Odb, outputs: Vec<TrackedOutput>) -> Result<Outputs, DiffError> {
+    outputs
+        .into_iter()
+        .map(|out| {
+            let oid = out.output.oid;
+            let obj = if oid.is_tree() && !oid_to_path(&odb.path, &oid).is_file() {
+                None
+            } else {
+                Some(odb.load_object(&oid)?)
+            };
+            Ok((out.path(), (oid, obj)))
+        })
+        .collect()
+}
+
+/// Diffs two sets of outputs, e.g. as recorded at two git revisions.
+pub fn diff_outputs(mut old: Outputs, mut new: Outputs) -> Diff {
+    let mut paths: Vec<_> = old.keys().chain(new.keys()).cloned().collect();
+    paths.sort_unstable();
+    paths.dedup();
+
+    let mut diff = Diff::default();
+    for path in paths {
+        let (old_oid, old_obj) = old.remove(&path).unzip();
+        let (new_oid, new_obj) = new.remove(&path).unzip();
+        if !matches!(old_obj, Some(None)) && !matches!(new_obj, Some(None)) {
+            diff = diff.merge(diff_obj(&path, old_obj.flatten(), new_obj.flatten()));
+        }
+        diff = diff.merge(diff_root(&path, old_oid.as_ref(), new_oid.as_ref()));
+    }
+    diff
+}
+
+pub fn diff_oid(odb: &Odb, old: Option<&Oid>, new: Option<&Oid>) -> Result<Diff, DiffError> {
     let old_obj = match old {
         None => None,
         Some(oid) => Some(odb.load_object(oid)?),
@@ -88,27 +399,28 @@ pub fn diff_object(old: Option<Object>, new: Option<Object>) -> Diff {
 }
 
 pub enum State<'a> {
-    Added(&'a str),
-    Modified(&'a str, &'a str),
-    Removed(&'a str),
-    Unchanged(&'a str),
+    Added(&'a Oid),
+    Modified(&'a Oid, &'a Oid),
+    Removed(&'a Oid),
+    /// The unchanged oid, or `None` if there is no object on either side.
+    Unchanged(Option<&'a Oid>),
 }
 
-pub fn diff_root_oid<'a>(old: Option<&'a str>, new: Option<&'a str>) -> State<'a> {
+pub fn diff_root_oid<'a>(old: Option<&'a Oid>, new: Option<&'a Oid>) -> State<'a> {
     match (old, new) {
         (None, Some(n)) => State::Added(n),
         (Some(o), Some(n)) if o != n => State::Modified(o, n),
         (Some(o), None) => State::Removed(o),
-        (Some(_), Some(n)) => State::Unchanged(n),
-        (None, None) => State::Unchanged(""),
+        (Some(_), Some(n)) => State::Unchanged(Some(n)),
+        (None, None) => State::Unchanged(None),
     }
 }
 
-pub fn diff_root(root: &Utf8Path, old: Option<&str>, new: Option<&str>) -> Diff {
+pub fn diff_root(root: &Utf8Path, old: Option<&Oid>, new: Option<&Oid>) -> Diff {
     let mut diff = Diff::default();
 
     let old_root = if let Some(old_oid) = old {
-        if old_oid.ends_with(".dir") {
+        if old_oid.is_tree() {
             root.join("")
         } else {
             root.to_path_buf()
@@ -118,7 +430,7 @@ pub fn diff_root(root: &Utf8Path, old: Option<&str>, new: Option<&str>) -> Diff
     };
 
     let new_root = if let Some(new_oid) = new {
-        if new_oid.ends_with(".dir") {
+        if new_oid.is_tree() {
             root.join("")
         } else {
             root.to_path_buf()
@@ -128,66 +440,61 @@ pub fn diff_root(root: &Utf8Path, old: Option<&str>, new: Option<&str>) -> Diff
     };
     match diff_root_oid(old, new) {
         State::Added(n) => {
-            diff.added.insert(new_root, n.to_string());
+            diff.added.insert(new_root, *n);
             diff
         }
         State::Modified(o, n) => {
-            diff.modified
-                .insert(new_root, (o.to_string(), n.to_string()));
+            diff.modified.insert(new_root, (*o, *n));
             diff
         }
         State::Removed(o) => {
-            diff.removed.insert(old_root, o.to_string());
+            diff.removed.insert(old_root, *o);
             diff
         }
         State::Unchanged(n) => {
-            diff.unchanged.insert(new_root, n.to_string());
+            if n.is_some() {
+                diff.unchanged += 1;
+            }
             diff
         }
     }
 }
 
+/// Diffs two trees by walking their sorted entries side by side.
 pub fn diff_tree(old: Option<Tree>, new: Option<Tree>) -> Diff {
-    let old_tree = old.unwrap_or_default();
-    let old_hm: HashMap<Utf8PathBuf, String> = old_tree
-        .entries
-        .into_iter()
-        .map(|e| (e.relpath, e.oid))
-        .collect();
+    let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+    let mut old_entries = old.iter().peekable();
+    let mut new_entries = new.iter().peekable();
 
-    let new_tree = new.unwrap_or_default();
-    let new_hm: HashMap<Utf8PathBuf, String> = new_tree
-        .entries
-        .into_iter()
-        .map(|e| (e.relpath, e.oid))
-        .collect();
-
-    let mut removed: HashMap<Utf8PathBuf, String> = HashMap::new();
-    for (key, value) in &old_hm {
-        if !new_hm.contains_key(key) {
-            removed.insert(key.clone(), value.clone());
-        }
-    }
-
-    let mut added: HashMap<Utf8PathBuf, String> = HashMap::new();
-    let mut modified: HashMap<Utf8PathBuf, (String, String)> = HashMap::new();
-    let mut unchanged: HashMap<Utf8PathBuf, String> = HashMap::new();
-    for (key, new_value) in new_hm {
-        if let Some(old_value) = old_hm.get(&key) {
-            if new_value == *old_value {
-                unchanged.insert(key, new_value.clone());
-            } else {
-                modified.insert(key, (old_value.clone(), new_value.clone()));
+    let mut diff = Diff::default();
+    loop {
+        let order = match (old_entries.peek(), new_entries.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(o), Some(n)) => o.relpath.as_str().cmp(n.relpath.as_str()),
+        };
+        match order {
+            Ordering::Less => {
+                let entry = old_entries.next().unwrap_or_else(|| unreachable!());
+                diff.removed.insert(entry.relpath, entry.oid);
+            }
+            Ordering::Greater => {
+                let entry = new_entries.next().unwrap_or_else(|| unreachable!());
+                diff.added.insert(entry.relpath, entry.oid);
+            }
+            Ordering::Equal => {
+                let (o, n) = old_entries
+                    .next()
+                    .zip(new_entries.next())
+                    .unwrap_or_else(|| unreachable!());
+                if o.oid == n.oid {
+                    diff.unchanged += 1;
+                } else {
+                    diff.modified.insert(n.relpath, (o.oid, n.oid));
+                }
             }
-        } else {
-            added.insert(key, new_value);
         }
     }
-
-    Diff {
-        added,
-        modified,
-        removed,
-        unchanged,
-    }
+    diff
 }
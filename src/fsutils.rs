@@ -41,3 +41,17 @@ pub fn protect_file(path: &Path) {
         m.permissions().set_readonly(true);
     }
 }
+
+/// Removes a file, symlink or directory tree, ignoring paths that don't exist.
+pub fn remove_path(path: impl AsRef<Path>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
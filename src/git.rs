@@ -0,0 +1,192 @@
+use crate::index::TrackedOutput;
+use crate::models::{Output, absolute_output_path, is_dvcfile, parse_outputs};
+use camino::{Utf8Path, Utf8PathBuf};
+use git2::{ObjectType, Repository, Sort, TreeWalkMode, TreeWalkResult};
+use std::path::Path;
+use std::str;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum GitError {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Utf8Error(#[from] str::Utf8Error),
+    #[error("failed to parse {0}: {1}")]
+    InvalidDvcFile(Utf8PathBuf, serde_yaml::Error),
+    #[error("Bare git repository found at {0}")]
+    BareGitRepo(std::path::PathBuf),
+    #[error("{0} is outside of the git working directory {1}")]
+    OutsideWorkdir(std::path::PathBuf, std::path::PathBuf),
+}
+
+/// Resolves a revision like `HEAD~1` or `main` to the tree of its commit.
+pub fn rev_tree<'r>(git_repo: &'r Repository, rev: &str) -> Result<git2::Tree<'r>, GitError> {
+    Ok(git_repo.revparse_single(rev)?.peel_to_tree()?)
+}
+
+/// Path of `root` relative to the git working directory.
+pub fn workdir_relpath<'a>(git_repo: &Repository, root: &'a Path) -> Result<&'a Path, GitError> {
+    let workdir = git_repo
+        .workdir()
+        .ok_or_else(|| GitError::BareGitRepo(git_repo.path().to_path_buf()))?;
+    // `workdir` may not be canonicalized while `root` is
+    let workdir = workdir
+        .canonicalize()
+        .unwrap_or_else(|_| workdir.to_path_buf());
+    root.strip_prefix(&workdir)
+        .map_err(|_| GitError::OutsideWorkdir(root.to_path_buf(), workdir))
+}
+
+/// Reads the contents of the file at `path` in `tree`, if it exists.
+pub fn read_file(
+    git_repo: &Repository,
+    tree: &git2::Tree,
+    path: &Path,
+) -> Result<Option<String>, GitError> {
+    let entry = match tree.get_path(path) {
+        Ok(entry) => entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let blob = git_repo.find_blob(entry.id())?;
+    Ok(Some(str::from_utf8(blob.content())?.to_owned()))
+}
+
+/// Collects the outputs of every `.dvc` file and `dvc.lock` found in `tree`
+/// under `subdir`, with dvcfile paths relative to `subdir`.
+pub fn outputs_in_tree(
+    git_repo: &Repository,
+    tree: &git2::Tree,
+    subdir: &Path,
+) -> Result<Vec<TrackedOutput>, GitError> {
+    let subtree = if subdir.as_os_str().is_empty() {
+        tree.clone()
+    } else {
+        match tree.get_path(subdir) {
+            Ok(entry) => git_repo.find_tree(entry.id())?,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let mut dvcfiles = Vec::new();
+    subtree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let Some(name) = entry.name() else {
+            return TreeWalkResult::Skip;
+        };
+        match entry.kind() {
+            Some(ObjectType::Tree) if name == ".dvc" || name == ".git" => TreeWalkResult::Skip,
+            Some(ObjectType::Blob) => {
+                let path = Utf8PathBuf::from(dir).join(name);
+                if is_dvcfile(&path) {
+                    dvcfiles.push((path, entry.id()));
+                }
+                TreeWalkResult::Ok
+            }
+            _ => TreeWalkResult::Ok,
+        }
+    })?;
+
+    let mut outputs = Vec::new();
+    for (dvcfile, id) in dvcfiles {
+        let blob = git_repo.find_blob(id)?;
+        let contents = str::from_utf8(blob.content())?;
+        let outs = parse_outputs(&dvcfile, contents)
+            .map_err(|e| GitError::InvalidDvcFile(dvcfile.clone(), e))?;
+        outputs.extend(outs.into_iter().map(|output| TrackedOutput {
+            dvcfile: dvcfile.clone(),
+            output,
+        }));
+    }
+    Ok(outputs)
+}
+
+/// Collects the outputs recorded at `rev` for the repository rooted at `root`.
+pub fn outputs_at_rev(
+    git_repo: &Repository,
+    rev: &str,
+    root: &Path,
+) -> Result<Vec<TrackedOutput>, GitError> {
+    let tree = rev_tree(git_repo, rev)?;
+    outputs_in_tree(git_repo, &tree, workdir_relpath(git_repo, root)?)
+}
+
+/// An output as recorded in a commit, see `output_history`.
+#[derive(Debug)]
+pub struct OutputVersion {
+    pub commit: git2::Oid,
+    /// Commit time in seconds since the epoch.
+    pub time: i64,
+    pub message: String,
+    /// `None` if the output was removed in this commit.
+    pub output: Option<Output>,
+}
+
+/// Finds the output at `path` recorded in `dvcfile` in `tree`.
+fn recorded_output(
+    git_repo: &Repository,
+    tree: &git2::Tree,
+    dvcfile: &Utf8Path,
+    path: &Utf8Path,
+) -> Result<Option<Output>, GitError> {
+    let Some(contents) = read_file(git_repo, tree, dvcfile.as_std_path())? else {
+        return Ok(None);
+    };
+    let outputs = parse_outputs(dvcfile, &contents)
+        .map_err(|e| GitError::InvalidDvcFile(dvcfile.to_path_buf(), e))?;
+    Ok(outputs
+        .into_iter()
+        .find(|out| absolute_output_path(dvcfile, &out.path) == path))
+}
+
+/// Walks the first-parent history of `rev` for commits that changed the
+/// output at `path` recorded in `dvcfile`, newest first. Both paths are
+/// relative to the git working directory.
+///
+/// Commits that touched `dvcfile` without changing the output's oid, e.g.
+/// another stage in `dvc.lock`, are skipped.
+pub fn output_history(
+    git_repo: &Repository,
+    rev: &str,
+    dvcfile: &Utf8Path,
+    path: &Utf8Path,
+) -> Result<Vec<OutputVersion>, GitError> {
+    let blob_id = |tree: &git2::Tree| tree.get_path(dvcfile.as_std_path()).ok().map(|e| e.id());
+
+    let mut walk = git_repo.revwalk()?;
+    walk.push(git_repo.revparse_single(rev)?.peel_to_commit()?.id())?;
+    walk.simplify_first_parent()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+
+    let mut versions = Vec::new();
+    for id in walk {
+        let commit = git_repo.find_commit(id?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let blob = blob_id(&tree);
+        if blob == parent_tree.as_ref().and_then(blob_id) {
+            continue;
+        }
+
+        let output = recorded_output(git_repo, &tree, dvcfile, path)?;
+        let parent_output = match &parent_tree {
+            Some(parent_tree) => recorded_output(git_repo, parent_tree, dvcfile, path)?,
+            None => None,
+        };
+        if output.as_ref().map(|out| &out.oid) == parent_output.as_ref().map(|out| &out.oid) {
+            continue;
+        }
+        versions.push(OutputVersion {
+            commit: commit.id(),
+            time: commit.time().seconds(),
+            message: commit.summary().unwrap_or_default().to_owned(),
+            output,
+        });
+    }
+    Ok(versions)
+}
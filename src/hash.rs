@@ -1,18 +1,28 @@
+use crate::objects::Oid;
 use md5::{Digest, Md5};
 use std::path::Path;
 use std::{fs, io};
 
-pub fn md5<R>(reader: &mut R) -> String
+pub fn md5_digest<R>(reader: &mut R) -> [u8; 16]
 where
     R: std::io::Read,
 {
     let mut hasher = Md5::new();
     let _: Result<_, _> = io::copy(reader, &mut hasher);
-    let hash = hasher.finalize();
-    base16ct::lower::encode_string(&hash)
+    hasher.finalize().into()
+}
+
+pub fn md5<R>(reader: &mut R) -> String
+where
+    R: std::io::Read,
+{
+    base16ct::lower::encode_string(&md5_digest(reader))
 }
 
-pub fn file_md5<P: AsRef<Path>>(path: &P) -> io::Result<String> {
+/// Hashes the file at `path`, failing if it cannot be read to the end.
+pub fn file_md5<P: AsRef<Path>>(path: &P) -> io::Result<Oid> {
     let mut file = fs::File::open(path)?;
-    Ok(md5(&mut file))
+    let mut hasher = Md5::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(Oid::md5(hasher.finalize().into()))
 }
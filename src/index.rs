@@ -0,0 +1,94 @@
+use crate::models::{Output, absolute_output_path, is_dvcfile, parse_outputs};
+use camino::{FromPathBufError, Utf8Path, Utf8PathBuf};
+use ignore::WalkBuilder;
+use std::fs;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum IndexError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    WalkError(#[from] ignore::Error),
+    #[error(transparent)]
+    FromPathBufError(#[from] FromPathBufError),
+    #[error("failed to parse {0}: {1}")]
+    InvalidDvcFile(Utf8PathBuf, serde_yaml::Error),
+}
+
+/// An output together with the `.dvc` file or `dvc.lock` that records it.
+#[derive(Debug)]
+pub struct TrackedOutput {
+    pub dvcfile: Utf8PathBuf,
+    pub output: Output,
+}
+
+impl TrackedOutput {
+    /// Path of the output, resolved relative to its dvcfile.
+    pub fn path(&self) -> Utf8PathBuf {
+        absolute_output_path(&self.dvcfile, &self.output.path)
+    }
+}
+
+/// Finds every `.dvc` file and `dvc.lock` under `root`, skipping `.dvcignore`d
+/// paths and the `.git`, `.dvc` and `.hg` directories.
+pub fn find_dvcfiles(root: &Utf8Path) -> Result<Vec<Utf8PathBuf>, IndexError> {
+    let mut dvcfiles = Vec::new();
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .add_custom_ignore_filename(".dvcignore")
+        .parents(true)
+        .filter_entry(|entry| {
+            let name = entry.file_name();
+            !(entry.file_type().is_some_and(|ft| ft.is_dir())
+                && (name == ".dvc" || name == ".git" || name == ".hg"))
+        })
+        .build();
+    for entry in walker {
+        let entry = entry?;
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        let path = Utf8PathBuf::try_from(entry.into_path())?;
+        if is_dvcfile(&path) {
+            dvcfiles.push(path);
+        }
+    }
+    dvcfiles.sort_unstable();
+    Ok(dvcfiles)
+}
+
+pub fn load_outputs(dvcfile: &Utf8Path) -> Result<Vec<TrackedOutput>, IndexError> {
+    let contents = fs::read_to_string(dvcfile)?;
+    let outputs = parse_outputs(dvcfile, &contents)
+        .map_err(|e| IndexError::InvalidDvcFile(dvcfile.to_path_buf(), e))?;
+    Ok(outputs
+        .into_iter()
+        .map(|output| TrackedOutput {
+            dvcfile: dvcfile.to_path_buf(),
+            output,
+        })
+        .collect())
+}
+
+pub fn collect_outputs(dvcfiles: &[Utf8PathBuf]) -> Result<Vec<TrackedOutput>, IndexError> {
+    let mut outputs = Vec::new();
+    for dvcfile in dvcfiles {
+        outputs.extend(load_outputs(dvcfile)?);
+    }
+    Ok(outputs)
+}
+
+/// Makes the dvcfile paths of `outputs` relative to `root`.
+pub fn relative_outputs(outputs: Vec<TrackedOutput>, root: &Utf8Path) -> Vec<TrackedOutput> {
+    outputs
+        .into_iter()
+        .map(|out| TrackedOutput {
+            dvcfile: out
+                .dvcfile
+                .strip_prefix(root)
+                .map_or(out.dvcfile.clone(), Utf8Path::to_path_buf),
+            output: out.output,
+        })
+        .collect()
+}
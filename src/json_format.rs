@@ -1,11 +1,73 @@
 use serde::Serialize;
-use serde_json;
+use serde_json::ser::{Formatter, Serializer};
+use std::io;
 
+/// Formats JSON like Python's `json.dumps()` with its default arguments, which
+/// DVC uses to serialize `.dir` objects: `", "` and `": "` separators, and
+/// every character outside printable ASCII escaped as `\uXXXX`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PythonFormatter;
+
+impl Formatter for PythonFormatter {
+    fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        writer.write_all(b": ")
+    }
+
+    /// Writes the parts of a string that `serde_json` leaves unescaped, i.e.
+    /// everything but quotes, backslashes and control characters, which it
+    /// already escapes the same way as Python.
+    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut start = 0;
+        for (i, c) in fragment.char_indices() {
+            if matches!(c, ' '..='~') {
+                continue;
+            }
+            writer.write_all(&fragment.as_bytes()[start..i])?;
+            let mut units = [0; 2];
+            for unit in c.encode_utf16(&mut units) {
+                write!(writer, "\\u{unit:04x}")?;
+            }
+            start = i + c.len_utf8();
+        }
+        writer.write_all(&fragment.as_bytes()[start..])
+    }
+}
+
+/// Serializes `value` exactly like `json.dumps(value)` in Python.
 pub fn to_string<T>(value: &T) -> serde_json::Result<String>
 where
     T: ?Sized + Serialize,
 {
-    Ok(serde_json::to_string(value)?
-        .replace(',', ", ")
-        .replace(':', ": "))
+    let mut buf = Vec::new();
+    value.serialize(&mut Serializer::with_formatter(&mut buf, PythonFormatter))?;
+    // only ASCII is written
+    String::from_utf8(buf).map_err(serde::ser::Error::custom)
 }
@@ -3,13 +3,18 @@ pub mod checkout;
 pub mod config;
 pub mod diff;
 pub mod fsutils;
+pub mod git;
 pub mod hash;
 pub mod ignore;
 pub mod ignorelist;
+pub mod index;
 pub mod json_format;
+pub mod merge;
 pub mod models;
 pub mod objects;
 pub mod odb;
+pub mod oid;
+pub mod patch;
 pub mod repo;
 pub mod state;
 pub mod status;
@@ -1,19 +1,39 @@
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::DateTime;
 use clap::{Parser, Subcommand};
-use console::style;
-use dvc_data::diff::Diff;
+use console::{Color, style};
+use dvc_data::build::{BuildOptions, BuildReport, SymlinkPolicy};
+use dvc_data::checkout::{CheckoutOptions, CheckoutResult, checkout_all};
+use dvc_data::diff::{ChangeType, DEFAULT_RENAME_LIMIT, Diff, Summary};
+use dvc_data::git::{output_history, outputs_at_rev, workdir_relpath};
 use dvc_data::ignore::get_ignore;
-use dvc_data::models::{default_dvcfile_path, path_relative_to_dvcfile};
+use dvc_data::index::{
+    TrackedOutput, collect_outputs, find_dvcfiles, load_outputs, relative_outputs,
+};
+use dvc_data::merge::{MergeError, merge_dvcfiles};
+use dvc_data::models::{default_dvcfile_path, is_dvcfile, path_relative_to_dvcfile, update_output};
+use dvc_data::objects::Oid;
+use dvc_data::odb::{Odb, oid_to_path};
+use dvc_data::patch::{is_text_path, read_text, table_summary, unified_diff};
 use dvc_data::repo::Repo;
-use dvc_data::status::{status, status_git};
-use dvc_data::{DvcFile, Object, build, checkout, checkout_obj, create_pool, transfer};
+use dvc_data::state::State;
+use dvc_data::status::{
+    CloudState, CloudStatus, OutputState, OutputStatus, cloud_status, status_all, status_git,
+    workspace_outputs,
+};
+use dvc_data::transfer::write_obj;
+use dvc_data::{DvcFile, Object, build, checkout_obj, create_pool, transfer};
 use dvc_data::{diff, ignorelist};
 use env_logger::Env;
 use git2::Repository;
+use indicatif::{DecimalBytes, HumanCount};
 use log::debug;
-use std::env::set_current_dir;
+use std::collections::BTreeMap;
+use std::env::{self, set_current_dir};
 use std::error::Error;
-use std::path::PathBuf;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::str;
 
 const ROOT: &str = "root";
@@ -39,28 +59,679 @@ enum Commands {
         write: bool,
         #[arg(short, long)]
         jobs: Option<usize>,
+        /// Hash every file without reusing recorded hashes. Hardlinks into
+        /// the cache are then hashed too, as they are only recognized through
+        /// the hash recorded for their path.
         #[arg(long)]
         no_state: bool,
+        #[arg(long)]
+        continue_on_error: bool,
+        /// How to handle symlinks: follow, skip or error.
+        #[arg(long, default_value = "follow")]
+        symlinks: SymlinkPolicy,
     },
     Add {
         path: Utf8PathBuf,
+        /// Hash every file without reusing recorded hashes. Hardlinks into
+        /// the cache are then hashed too, as they are only recognized through
+        /// the hash recorded for their path.
         #[arg(long)]
         no_state: bool,
+        #[arg(long)]
+        continue_on_error: bool,
+        /// How to handle symlinks: follow, skip or error.
+        #[arg(long, default_value = "follow")]
+        symlinks: SymlinkPolicy,
     },
     CheckoutObject {
-        oid: String,
+        oid: Oid,
         path: Utf8PathBuf,
+        #[arg(long)]
+        allow_missing: bool,
+        #[arg(long)]
+        verify: bool,
     },
     Checkout {
-        path: Utf8PathBuf,
+        /// `.dvc` files or `dvc.lock` to checkout, defaults to the whole repository
+        targets: Vec<Utf8PathBuf>,
+        /// Checkout every dvcfile found under directory targets
+        #[arg(short = 'R', long)]
+        recursive: bool,
+        #[arg(long)]
+        allow_missing: bool,
+        #[arg(long)]
+        verify: bool,
     },
+    /// Diff tracked outputs between two git revisions, or a revision and the workspace
     Diff {
-        old: String,
-        new: Option<String>,
+        a_rev: String,
+        b_rev: Option<String>,
+        /// Print changes as JSON
+        #[arg(long, conflicts_with = "md")]
+        json: bool,
+        /// Print changes as a markdown table
+        #[arg(long)]
+        md: bool,
+        /// Skip rename detection for oids shared by more than this many paths
+        #[arg(long, default_value_t = DEFAULT_RENAME_LIMIT)]
+        rename_limit: usize,
+        /// Summarize changes per directory, up to the given depth
+        #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "1")]
+        summary: Option<usize>,
+        /// Show line diffs of modified text files
+        #[arg(long, conflicts_with_all = ["json", "md", "summary"])]
+        patch: bool,
+    },
+    DiffObject {
+        old: Oid,
+        new: Option<Oid>,
+        /// Print changes as JSON
+        #[arg(long, conflicts_with = "md")]
+        json: bool,
+        /// Print changes as a markdown table
+        #[arg(long)]
+        md: bool,
+        /// Skip rename detection for oids shared by more than this many paths
+        #[arg(long, default_value_t = DEFAULT_RENAME_LIMIT)]
+        rename_limit: usize,
+        /// Summarize changes per directory, up to the given depth
+        #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "1")]
+        summary: Option<usize>,
+        /// Show line diffs of modified text files
+        #[arg(long, conflicts_with_all = ["json", "md", "summary"])]
+        patch: bool,
     },
     Status {
-        path: Utf8PathBuf,
+        /// `.dvc` files or `dvc.lock` to check, defaults to the whole repository
+        targets: Vec<Utf8PathBuf>,
+        /// Check every dvcfile found under directory targets
+        #[arg(short = 'R', long)]
+        recursive: bool,
+        /// Print the status as JSON
+        #[arg(long, conflicts_with = "md")]
+        json: bool,
+        /// Print the status as a markdown table
+        #[arg(long)]
+        md: bool,
+        /// Skip rename detection for oids shared by more than this many paths
+        #[arg(long, default_value_t = DEFAULT_RENAME_LIMIT)]
+        rename_limit: usize,
+        /// Summarize changes per directory, up to the given depth
+        #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "1")]
+        summary: Option<usize>,
+        /// Git revision to compare dvcfile targets with
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// Compare the cache with a remote instead of the workspace
+        #[arg(short, long, conflicts_with = "summary")]
+        cloud: bool,
+        /// Remote to compare with, defaults to core.remote
+        #[arg(short, long, requires = "cloud")]
+        remote: Option<String>,
     },
+    /// Three-way merge `.dvc` files, for use as a git merge driver:
+    /// `dvc-data merge-driver %O %A %B`
+    MergeDriver {
+        /// Common ancestor's version
+        base: PathBuf,
+        /// Current version, overwritten with the merged result
+        ours: PathBuf,
+        /// Other branch's version
+        theirs: PathBuf,
+    },
+    Log {
+        /// `.dvc` file or tracked output to show the history of
+        target: Utf8PathBuf,
+        /// Git revision to start from
+        #[arg(long, default_value = "HEAD")]
+        rev: String,
+        /// Show at most this many commits
+        #[arg(short = 'n', long)]
+        max_count: Option<usize>,
+    },
+}
+
+fn relative_to<'a>(path: &'a Utf8Path, cwd: &Utf8Path) -> &'a Utf8Path {
+    path.strip_prefix(cwd).unwrap_or(path)
+}
+
+fn change_label(typ: ChangeType) -> &'static str {
+    match typ {
+        ChangeType::Added => "added",
+        ChangeType::Modified => "modified",
+        ChangeType::Removed => "deleted",
+        ChangeType::Renamed => "renamed",
+    }
+}
+
+fn state_label(state: OutputState) -> &'static str {
+    match state {
+        OutputState::UpToDate => "up to date",
+        OutputState::Modified => "modified",
+        OutputState::Deleted => "deleted",
+        OutputState::NotInCache => "not in cache",
+    }
+}
+
+fn cloud_label(state: CloudState) -> &'static str {
+    match state {
+        CloudState::Both => "in both",
+        CloudState::LocalOnly => "local only",
+        CloudState::RemoteOnly => "remote only",
+        CloudState::Missing => "missing",
+    }
+}
+
+/// Prints objects that are not both in the cache and the remote, followed by
+/// counts per state.
+fn print_cloud_status(
+    statuses: Vec<CloudStatus>,
+    cwd: &Utf8Path,
+    json: bool,
+    md: bool,
+) -> Result<(), Box<dyn Error>> {
+    let statuses: Vec<_> = statuses
+        .into_iter()
+        .map(|st| CloudStatus {
+            path: relative_to(&st.path, cwd).to_path_buf(),
+            ..st
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+        return Ok(());
+    }
+    if md {
+        let rows: Vec<_> = statuses
+            .iter()
+            .map(|st| {
+                vec![
+                    cloud_label(st.state).to_owned(),
+                    st.path.to_string(),
+                    st.oid.to_string(),
+                ]
+            })
+            .collect();
+        print_markdown_table(&["State", "Path", "Oid"], &rows);
+        return Ok(());
+    }
+
+    let mut counts: BTreeMap<CloudState, usize> = BTreeMap::new();
+    for st in &statuses {
+        *counts.entry(st.state).or_default() += 1;
+        if st.state != CloudState::Both {
+            let line = format!("{}: {}", cloud_label(st.state), st.path);
+            let color = if st.state == CloudState::LocalOnly {
+                Color::Yellow
+            } else {
+                Color::Red
+            };
+            println!("\t{}", style(line).fg(color));
+        }
+    }
+    let counts: Vec<_> = counts
+        .into_iter()
+        .map(|(state, count)| format!("{} {}", HumanCount(count as u64), cloud_label(state)))
+        .collect();
+    if counts.is_empty() {
+        println!("No objects to compare.");
+    } else {
+        println!("Objects: {}", counts.join(", "));
+    }
+    Ok(())
+}
+
+fn print_diff(diff: &Diff, cwd: &Utf8Path, color: Color) {
+    for change in diff.changes() {
+        let path = relative_to(change.path, cwd);
+        let line = match change.old_path {
+            Some(old_path) => {
+                let old_path = relative_to(old_path, cwd);
+                format!("{}: {old_path} -> {path}", change_label(change.typ))
+            }
+            None => format!("{}: {path}", change_label(change.typ)),
+        };
+        println!("\t{}", style(line).fg(color));
+    }
+}
+
+fn print_changes(diff: &Diff) {
+    for change in diff.changes() {
+        let path = change.path;
+        if let (Some(old_path), Some(oid)) = (change.old_path, change.new) {
+            println!("renamed: {old_path} ({oid}) -> {path} ({oid})");
+            continue;
+        }
+        match (change.old, change.new) {
+            (None, Some(new)) => println!("added: {path} ({new})"),
+            (Some(old), None) => println!("removed: {path} ({old})"),
+            (Some(old), Some(new)) => {
+                println!("modified: {path} ({old}) -> {path} ({new})");
+            }
+            (None, None) => (),
+        }
+    }
+}
+
+fn signed(added: u64, removed: u64) -> (char, u64) {
+    if added >= removed {
+        ('+', added - removed)
+    } else {
+        ('-', removed - added)
+    }
+}
+
+fn summary_cells(dir: String, summary: &Summary) -> Vec<String> {
+    let (files_sign, files) = signed(summary.added, summary.removed);
+    let (bytes_sign, bytes) = signed(summary.bytes_added, summary.bytes_removed);
+    vec![
+        dir,
+        format!("{files_sign}{}", HumanCount(files)),
+        format!("{bytes_sign}{}", DecimalBytes(bytes)),
+        summary.added.to_string(),
+        summary.modified.to_string(),
+        summary.removed.to_string(),
+        summary.renamed.to_string(),
+    ]
+}
+
+/// Formats a summary like "+1,203 files, +4.20 GB, 2 modified".
+fn summary_line(summary: &Summary) -> Result<String, std::fmt::Error> {
+    let cells = summary_cells(String::new(), summary);
+    let mut line = format!("{} files, {}", cells[1], cells[2]);
+    if summary.modified > 0 {
+        write!(line, ", {} modified", cells[4])?;
+    }
+    if summary.renamed > 0 {
+        write!(line, ", {} renamed", cells[6])?;
+    }
+    Ok(line)
+}
+
+/// Prints `Diff::summary` for each labelled diff, e.g. "train/: +1,203 files, +4.20 GB".
+/// A single diff is expected to have an empty label.
+fn print_summaries(
+    diffs: &[(&str, &Diff)],
+    depth: usize,
+    json: bool,
+    md: bool,
+) -> Result<(), Box<dyn Error>> {
+    if json {
+        let report = if let [("", diff)] = diffs {
+            serde_json::to_value(diff.summary(depth))?
+        } else {
+            let mut report = serde_json::Map::new();
+            for (label, diff) in diffs {
+                report.insert(
+                    (*label).to_owned(),
+                    serde_json::to_value(diff.summary(depth))?,
+                );
+            }
+            serde_json::Value::Object(report)
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if md {
+        let labelled = !matches!(diffs, [("", _)]);
+        let mut rows = Vec::new();
+        for (label, diff) in diffs {
+            for (dir, summary) in &diff.summary(depth) {
+                let mut row = summary_cells(dir.clone(), summary);
+                if labelled {
+                    row.insert(0, (*label).to_owned());
+                }
+                rows.push(row);
+            }
+        }
+        let mut headers = vec![
+            "Path", "Files", "Size", "Added", "Modified", "Removed", "Renamed",
+        ];
+        if labelled {
+            headers.insert(0, "Stage");
+        }
+        print_markdown_table(&headers, &rows);
+    } else {
+        for (label, diff) in diffs {
+            let summaries = diff.summary(depth);
+            let indent = if label.is_empty() { "" } else { "\t" };
+            if !label.is_empty() && !summaries.is_empty() {
+                println!("DVC {label} changes:");
+            }
+            for (dir, summary) in &summaries {
+                println!("{indent}{dir}: {}", summary_line(summary)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints line diffs of modified text files, reading new files from the
+/// workspace under `workdir` if they are not in the cache.
+fn print_patches(odb: &Odb, diff: &Diff, workdir: Option<&Utf8Path>) {
+    for change in diff.changes() {
+        let (ChangeType::Modified, Some(old), Some(new)) = (change.typ, change.old, change.new)
+        else {
+            continue;
+        };
+        if !is_text_path(change.path) {
+            continue;
+        }
+        let workspace_path = workdir.map(|dir| dir.join(change.path).into_std_path_buf());
+        let (Some(old_text), Some(new_text)) = (
+            read_text(odb, old, None),
+            read_text(odb, new, workspace_path.as_deref()),
+        ) else {
+            debug!("skipping patch for {}", change.path);
+            continue;
+        };
+
+        println!();
+        if matches!(change.path.extension(), Some("csv" | "tsv")) {
+            let summary = table_summary(change.path, &old_text, &new_text);
+            println!(
+                "{}: {} rows added, {} rows removed",
+                change.path, summary.rows_added, summary.rows_removed
+            );
+            if !summary.columns_added.is_empty() {
+                println!("columns added: {}", summary.columns_added.join(", "));
+            }
+            if !summary.columns_removed.is_empty() {
+                println!("columns removed: {}", summary.columns_removed.join(", "));
+            }
+        }
+        print!("{}", unified_diff(change.path, &old_text, &new_text));
+    }
+}
+
+/// Rebases every path in `diff` onto `cwd` for display.
+fn relative_diff(diff: Diff, cwd: &Utf8Path) -> Diff {
+    let rel = |path: Utf8PathBuf| relative_to(&path, cwd).to_path_buf();
+    Diff {
+        added: diff.added.into_iter().map(|(p, v)| (rel(p), v)).collect(),
+        modified: diff
+            .modified
+            .into_iter()
+            .map(|(p, v)| (rel(p), v))
+            .collect(),
+        removed: diff.removed.into_iter().map(|(p, v)| (rel(p), v)).collect(),
+        unchanged: diff.unchanged,
+        renamed: diff
+            .renamed
+            .into_iter()
+            .map(|(p, (old_p, v))| (rel(p), (rel(old_p), v)))
+            .collect(),
+        sizes: diff.sizes.into_iter().map(|(p, v)| (rel(p), v)).collect(),
+    }
+}
+
+fn print_markdown_table(headers: &[&str], rows: &[Vec<String>]) {
+    let escape = |cell: &str| cell.replace('|', "\\|");
+    println!("| {} |", headers.join(" | "));
+    println!("|{}", "---|".repeat(headers.len()));
+    for row in rows {
+        let cells: Vec<_> = row.iter().map(|cell| escape(cell)).collect();
+        println!("| {} |", cells.join(" | "));
+    }
+}
+
+fn diff_rows(diff: &Diff) -> Vec<Vec<String>> {
+    diff.changes()
+        .into_iter()
+        .map(|change| {
+            vec![
+                change_label(change.typ).to_owned(),
+                match change.old_path {
+                    Some(old_path) => format!("{old_path} -> {}", change.path),
+                    None => change.path.to_string(),
+                },
+                change.old.map(Oid::to_string).unwrap_or_default(),
+                change.new.map(Oid::to_string).unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+/// Finds the dvcfile recording `target`, which is either a `.dvc` file or a
+/// tracked output, and the absolute path of the output.
+fn find_output(
+    root: &Utf8Path,
+    target: &Utf8Path,
+) -> Result<(Utf8PathBuf, Utf8PathBuf), Box<dyn Error>> {
+    let target = Utf8PathBuf::try_from(env::current_dir()?.join(target))?;
+    let parent = target.parent().unwrap_or(&target);
+    let target = Utf8PathBuf::try_from(fs::canonicalize(parent)?)?
+        .join(target.file_name().unwrap_or_default());
+    if target.extension() == Some("dvc") {
+        let outputs = load_outputs(&target)?;
+        let [out] = outputs.as_slice() else {
+            return Err(format!("expected a single output in '{target}'").into());
+        };
+        return Ok((target.clone(), out.path()));
+    }
+    collect_outputs(&find_dvcfiles(root)?)?
+        .into_iter()
+        .find(|out| out.path() == target)
+        .map(|out| {
+            let path = out.path();
+            (out.dvcfile, path)
+        })
+        .ok_or_else(|| format!("'{target}' is not tracked by any dvcfile").into())
+}
+
+/// Prints the commits in which the output recorded in `dvcfile` at `path`
+/// changed, with a summary of the changes against its previous version.
+fn print_log(
+    repo: &Repo,
+    git_repo: &Repository,
+    rev: &str,
+    dvcfile: &Utf8Path,
+    path: &Utf8Path,
+    max_count: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let relpath = |path: &Utf8Path| -> Result<Utf8PathBuf, Box<dyn Error>> {
+        Ok(Utf8PathBuf::try_from(
+            workdir_relpath(git_repo, path.as_std_path())?.to_path_buf(),
+        )?)
+    };
+    let versions = output_history(git_repo, rev, &relpath(dvcfile)?, &relpath(path)?)?;
+    let count = max_count.unwrap_or(versions.len()).min(versions.len());
+    for (i, version) in versions.iter().take(count).enumerate() {
+        let previous = versions.get(i + 1).and_then(|v| v.output.as_ref());
+        let date = DateTime::from_timestamp(version.time, 0)
+            .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        println!("{}", style(format!("commit {}", version.commit)).yellow());
+        println!("Date:   {date}");
+        println!("\n    {}\n", version.message);
+
+        let Some(out) = &version.output else {
+            println!("    removed");
+            continue;
+        };
+        let mut line = format!("    oid: {}", out.oid);
+        if let Some(size) = out.size {
+            write!(line, ", size: {}", DecimalBytes(size))?;
+        }
+        if let Some(nfiles) = out.nfiles {
+            write!(line, ", nfiles: {}", HumanCount(nfiles as u64))?;
+        }
+        println!("{line}");
+
+        let old = previous.map(|out| &out.oid);
+        let in_cache = |oid: &Oid| !oid.is_tree() || oid_to_path(&repo.odb.path, oid).is_file();
+        if !in_cache(&out.oid) || !old.is_none_or(in_cache) {
+            println!("    changes: unknown, objects not in cache");
+        } else if out.oid.is_tree() {
+            let mut d = diff::diff_oid(&repo.odb, old, Some(&out.oid))?
+                .detect_renames(DEFAULT_RENAME_LIMIT);
+            d.fill_sizes(&repo.odb, None);
+            let summary = d.summary(0).into_values().next().unwrap_or_default();
+            println!("    changes: {}", summary_line(&summary)?);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Finds the `.dvc` file tracking a directory that contains `path`.
+fn find_tracked_dir(root: &Path, path: &Utf8Path) -> Result<Option<TrackedOutput>, Box<dyn Error>> {
+    for dir in path.ancestors().skip(1) {
+        if !dir.as_std_path().starts_with(root) {
+            break;
+        }
+        let dvcfile = default_dvcfile_path(dir);
+        if !dvcfile.is_file() {
+            continue;
+        }
+        let tracked = load_outputs(&dvcfile)?
+            .into_iter()
+            .find(|out| out.path() == dir && out.output.oid.is_tree());
+        if tracked.is_some() {
+            return Ok(tracked);
+        }
+    }
+    Ok(None)
+}
+
+/// Adds `path` inside the tracked directory `out` by editing its recorded
+/// tree, without walking the rest of the directory. If `path` no longer
+/// exists, its entries are removed instead. Returns the updated dvcfile.
+fn add_to_tracked_dir(
+    root: &Path,
+    odb: &Odb,
+    state: Option<&State>,
+    threads: usize,
+    options: &BuildOptions,
+    out: &TrackedOutput,
+    path: &Utf8Path,
+) -> Result<Utf8PathBuf, Box<dyn Error>> {
+    let Object::Tree(mut tree) = odb.load_object(&out.output.oid)? else {
+        return Err(format!("'{}' is not a directory", out.path()).into());
+    };
+    let relpath = odb.normalization.relpath(path.strip_prefix(out.path())?);
+    let cached_size = |oid: &Oid| fs::metadata(oid_to_path(&odb.path, oid)).map(|meta| meta.len());
+
+    let mut size = out.output.size;
+    let obj = if fs::symlink_metadata(path).is_ok() {
+        let parent = path.parent().unwrap_or(path);
+        let ignore = get_ignore(root, parent.as_std_path())?;
+        let (obj, added, report) = build(odb, path, state, &ignore, threads, options)?;
+        report_build(&report);
+        transfer(odb, path.as_std_path(), &obj, &report.sources)?;
+        size = size.map(|size| size + added);
+        Some(obj)
+    } else {
+        None
+    };
+    for entry in tree.replace_path(&relpath, obj.as_ref())? {
+        size = size
+            .zip(cached_size(&entry.oid).ok())
+            .and_then(|(size, removed)| size.checked_sub(removed));
+    }
+
+    let (serialized, oid) = tree.digest()?;
+    write_obj(&odb.path, &oid, &serialized)?;
+    let contents = fs::read_to_string(&out.dvcfile)?;
+    let contents = update_output(&contents, &out.output.path, oid, size, Some(tree.len()))?;
+    fs::write(&out.dvcfile, contents)?;
+    Ok(out.dvcfile.clone())
+}
+
+/// Adds `path` to the tracked directory containing it, if any, and reports
+/// the updated dvcfile. Returns whether such a directory was found.
+fn update_tracked_dir(
+    repo: &Repo,
+    state: Option<&State>,
+    threads: usize,
+    options: &BuildOptions,
+    path: &Utf8Path,
+) -> Result<bool, Box<dyn Error>> {
+    let Some(out) = find_tracked_dir(&repo.root, path)? else {
+        return Ok(false);
+    };
+    let dvcfile = add_to_tracked_dir(&repo.root, &repo.odb, state, threads, options, &out, path)?;
+    eprintln!(
+        "    {} {}",
+        style("Updated").green().bold(),
+        relative_to(&dvcfile, &Utf8PathBuf::try_from(env::current_dir()?)?)
+    );
+    Ok(true)
+}
+
+/// Resolves dvcfile targets, defaulting to every dvcfile in the repository.
+fn find_targets(
+    repo: &Repo,
+    targets: Vec<Utf8PathBuf>,
+    recursive: bool,
+) -> Result<Vec<Utf8PathBuf>, Box<dyn Error>> {
+    if targets.is_empty() {
+        let root = Utf8PathBuf::try_from(repo.root.clone())?;
+        return Ok(find_dvcfiles(&root)?);
+    }
+    let mut dvcfiles = Vec::new();
+    for target in targets {
+        if recursive && target.is_dir() {
+            dvcfiles.extend(find_dvcfiles(&target)?);
+        } else {
+            dvcfiles.push(target);
+        }
+    }
+    Ok(dvcfiles)
+}
+
+fn report_checkout(result: &CheckoutResult) -> Result<(), Box<dyn Error>> {
+    for (path, oid) in &result.missing {
+        eprintln!(
+            "    {} {} ({oid})",
+            style("Missing").red().bold(),
+            path.as_str()
+        );
+    }
+    for (path, oid) in &result.corrupted {
+        eprintln!(
+            "    {} {} ({oid})",
+            style("Corrupted").red().bold(),
+            path.as_str()
+        );
+    }
+    if result.missing.is_empty() && result.corrupted.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} object(s) missing from cache, {} corrupted",
+            result.missing.len(),
+            result.corrupted.len()
+        )
+        .into())
+    }
+}
+
+/// Warns about files that were left out of a build.
+#[allow(clippy::unnecessary_debug_formatting)]
+fn report_build(report: &BuildReport) {
+    for path in &report.non_utf8 {
+        // escape the invalid bytes, which `display()` would replace
+        eprintln!(
+            "    {} {path:?} (file name is not valid UTF-8)",
+            style("Skipped").yellow().bold(),
+        );
+    }
+    for (path, e) in &report.errors {
+        eprintln!("    {} {path:?}: {e}", style("Failed").red().bold());
+    }
+    if !report.non_utf8.is_empty() {
+        eprintln!(
+            "{} {} file(s) were not added, rename them to track them",
+            style("warning:").yellow().bold(),
+            report.non_utf8.len()
+        );
+    }
+    if !report.errors.is_empty() {
+        eprintln!(
+            "{} {} path(s) could not be built and were not added",
+            style("warning:").yellow().bold(),
+            report.errors.len()
+        );
+    }
 }
 
 #[allow(clippy::too_many_lines)]
@@ -78,8 +749,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             write,
             jobs,
             no_state,
+            continue_on_error,
+            symlinks,
         } => {
             let repo = Repo::discover(None)?;
+            let options = BuildOptions {
+                continue_on_error,
+                symlinks,
+            };
             let threads = create_pool(jobs.or(repo.config.core.checksum_jobs))?;
             let state = if no_state { None } else { Some(&repo.state) };
             eprintln!("    {} files", style("Staging").green().bold());
@@ -92,16 +769,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .expect("failed to determine parent directory")
                     .as_std_path(),
             )?;
-            let (obj, size) = build(&repo.odb, &abspath, state, &ignore, threads)?;
+            let (obj, size, report) =
+                build(&repo.odb, &abspath, state, &ignore, threads, &options)?;
+            report_build(&report);
 
             match &obj {
-                Object::Tree(t) => debug!("size: {}, nfiles: {}", size, t.entries.len()),
+                Object::Tree(t) => debug!("size: {}, nfiles: {}", size, t.len()),
                 Object::HashFile(_) => debug!("size: {size}"),
             }
 
             let oid = if write {
                 eprintln!("    {} files", style("Transferring").green().bold());
-                transfer(&repo.odb, abspath.as_std_path(), &obj)?
+                transfer(&repo.odb, abspath.as_std_path(), &obj, &report.sources)?
             } else {
                 match obj {
                     Object::Tree(t) => t.digest()?.1,
@@ -112,13 +791,25 @@ fn main() -> Result<(), Box<dyn Error>> {
 
             Ok(())
         }
-        Commands::Add { path, no_state } => {
+        Commands::Add {
+            path,
+            no_state,
+            continue_on_error,
+            symlinks,
+        } => {
             let repo = Repo::discover(None)?;
+            let options = BuildOptions {
+                continue_on_error,
+                symlinks,
+            };
             let state = if no_state { None } else { Some(&repo.state) };
             let threads = create_pool(repo.config.core.checksum_jobs)?;
             eprintln!("    {} files", style("Staging").green().bold());
 
             let abspath = camino::absolute_utf8(&path)?;
+            if update_tracked_dir(&repo, state, threads, &options, &abspath)? {
+                return Ok(());
+            }
             let ignore = get_ignore(
                 &repo.root,
                 abspath
@@ -126,12 +817,14 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .expect("failed to determine parent directory")
                     .as_std_path(),
             )?;
-            let (obj, size) = build(&repo.odb, &abspath, state, &ignore, threads)?;
+            let (obj, size, report) =
+                build(&repo.odb, &abspath, state, &ignore, threads, &options)?;
+            report_build(&report);
             eprintln!("    {} files", style("Transferring").green().bold());
 
-            let oid = transfer(&repo.odb, abspath.as_std_path(), &obj)?;
+            let oid = transfer(&repo.odb, abspath.as_std_path(), &obj, &report.sources)?;
             let nfiles = match obj {
-                Object::Tree(t) => Some(t.entries.len()),
+                Object::Tree(t) => Some(t.len()),
                 Object::HashFile(_) => None,
             };
             let dvcfile = default_dvcfile_path(&abspath);
@@ -154,37 +847,124 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
             Ok(())
         }
-        Commands::CheckoutObject { oid, path } => {
+        Commands::CheckoutObject {
+            oid,
+            path,
+            allow_missing,
+            verify,
+        } => {
             let repo = Repo::discover(None)?;
-            checkout_obj(&repo.odb, &oid, &path, &repo.config.cache.typ)?;
-            Ok(())
+            let options = CheckoutOptions {
+                allow_missing,
+                verify,
+            };
+            let result = checkout_obj(
+                &repo.odb,
+                &oid,
+                &path,
+                &repo.config.cache.typ,
+                Some(&repo.state),
+                &options,
+            )?;
+            report_checkout(&result)
         }
-        Commands::Checkout { path } => {
+        Commands::Checkout {
+            targets,
+            recursive,
+            allow_missing,
+            verify,
+        } => {
             let repo = Repo::discover(None)?;
-            checkout(&repo.odb, &path, &repo.config.cache.typ)?;
-            Ok(())
+            create_pool(repo.config.core.checksum_jobs)?;
+            let dvcfiles = find_targets(&repo, targets, recursive)?;
+            let options = CheckoutOptions {
+                allow_missing,
+                verify,
+            };
+            let result = checkout_all(
+                &repo.odb,
+                &dvcfiles,
+                &repo.config.cache.typ,
+                Some(&repo.state),
+                &options,
+            )?;
+            report_checkout(&result)
         }
-        Commands::Diff { old, new } => {
+        Commands::Diff {
+            a_rev,
+            b_rev,
+            json,
+            md,
+            rename_limit,
+            summary,
+            patch,
+        } => {
             let repo = Repo::discover(None)?;
-            let d = diff::diff_oid(&repo.odb, Some(&old), new.as_deref())?;
+            let threads = create_pool(repo.config.core.checksum_jobs)?;
+            let git_repo = Repository::discover(&repo.root)?;
+            let root = Utf8PathBuf::try_from(repo.root.clone())?;
 
-            for (path, key) in d.added {
-                println!("added: {} ({})", path.as_str(), key);
+            let old = outputs_at_rev(&git_repo, &a_rev, &repo.root)?;
+            let old = diff::load_output_objects(&repo.odb, old)?;
+            // objects missing from the cache are only read from the workspace
+            // when it is what is compared
+            let workdir = b_rev.is_none().then_some(root.as_path());
+            let new = if let Some(rev) = b_rev {
+                let new = outputs_at_rev(&git_repo, &rev, &repo.root)?;
+                diff::load_output_objects(&repo.odb, new)?
+            } else {
+                let outputs = collect_outputs(&find_dvcfiles(&root)?)?;
+                let outputs = relative_outputs(outputs, &root);
+                workspace_outputs(&repo.odb, Some(&repo.state), &root, threads, &outputs)?
+            };
+            let mut d = diff::diff_outputs(old, new).detect_renames(rename_limit);
+            d.fill_sizes(&repo.odb, workdir);
+
+            if let Some(depth) = summary {
+                print_summaries(&[("", &d)], depth, json, md)?;
+            } else if json {
+                println!("{}", serde_json::to_string_pretty(&d)?);
+            } else if md {
+                print_markdown_table(&["Type", "Path", "Old", "New"], &diff_rows(&d));
+            } else {
+                print_changes(&d);
+                if patch {
+                    print_patches(&repo.odb, &d, workdir);
+                }
             }
-            for (path, key) in d.removed {
-                println!("removed: {} ({})", path.as_str(), key);
+            Ok(())
+        }
+        Commands::DiffObject {
+            old,
+            new,
+            json,
+            md,
+            rename_limit,
+            summary,
+            patch,
+        } => {
+            let repo = Repo::discover(None)?;
+            let mut d =
+                diff::diff_oid(&repo.odb, Some(&old), new.as_ref())?.detect_renames(rename_limit);
+            d.fill_sizes(&repo.odb, None);
+
+            if let Some(depth) = summary {
+                print_summaries(&[("", &d)], depth, json, md)?;
+                return Ok(());
             }
-            for (path, (new, old)) in d.modified {
-                println!(
-                    "modified: {} ({}) -> {} ({})",
-                    path.as_str(),
-                    old,
-                    path.as_str(),
-                    new
-                );
+            if json || md {
+                let root = diff::diff_root(Utf8Path::new(ROOT), Some(&old), new.as_ref());
+                let d = d.merge(root);
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&d)?);
+                } else {
+                    print_markdown_table(&["Type", "Path", "Old", "New"], &diff_rows(&d));
+                }
+                return Ok(());
             }
 
-            match diff::diff_root_oid(Some(&old), new.as_deref()) {
+            print_changes(&d);
+            match diff::diff_root_oid(Some(&old), new.as_ref()) {
                 diff::State::Added(n) => println!("added: {ROOT} ({n})"),
                 diff::State::Modified(o, n) => {
                     println!("modified: {ROOT} ({o}) -> {ROOT} ({n})");
@@ -192,65 +972,194 @@ fn main() -> Result<(), Box<dyn Error>> {
                 diff::State::Removed(o) => println!("removed: {ROOT} ({o})"),
                 diff::State::Unchanged(_) => (),
             }
+            if patch {
+                print_patches(&repo.odb, &d, None);
+            }
             Ok(())
         }
-        Commands::Status { path } => {
+        Commands::MergeDriver { base, ours, theirs } => {
+            let repo = Repo::discover(None)?;
+            let result = merge_dvcfiles(
+                &repo.odb,
+                &fs::read_to_string(base)?,
+                &fs::read_to_string(&ours)?,
+                &fs::read_to_string(theirs)?,
+            );
+            match result {
+                Ok(merged) => Ok(fs::write(ours, merged)?),
+                Err(MergeError::Conflict(path, conflicts)) => {
+                    for relpath in &conflicts {
+                        eprintln!(
+                            "    {} {}",
+                            style("Conflict").red().bold(),
+                            path.join(relpath)
+                        );
+                    }
+                    Err(MergeError::Conflict(path, conflicts).into())
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+        Commands::Log {
+            target,
+            rev,
+            max_count,
+        } => {
+            let repo = Repo::discover(None)?;
+            let git_repo = Repository::discover(&repo.root)?;
+            let root = Utf8PathBuf::try_from(repo.root.clone())?;
+            let (dvcfile, path) = find_output(&root, &target)?;
+            print_log(&repo, &git_repo, &rev, &dvcfile, &path, max_count)
+        }
+        Commands::Status {
+            targets,
+            recursive,
+            json,
+            md,
+            rename_limit,
+            summary,
+            rev,
+            cloud,
+            remote,
+        } => {
             let repo = Repo::discover(None)?;
             let threads = create_pool(repo.config.core.checksum_jobs)?;
             let state = Some(&repo.state);
+            let cwd = Utf8PathBuf::try_from(fs::canonicalize(env::current_dir()?)?)?;
 
-            let abspath = camino::absolute_utf8(&path)?;
-            let ignore = get_ignore(
-                &repo.root,
-                abspath
-                    .parent()
-                    .expect("failed to determine parent directory")
-                    .as_std_path(),
-            )?;
+            if cloud {
+                let remote = repo.remote_odb(remote.as_deref())?;
+                let dvcfiles = find_targets(&repo, targets, recursive)?;
+                let outputs = collect_outputs(&dvcfiles)?;
+                let statuses = cloud_status(&repo.odb, &remote, &outputs)?;
+                return print_cloud_status(statuses, &cwd, json, md);
+            }
 
-            let diff = match Repository::discover(repo.root) {
-                Ok(git_repo) => status_git(&git_repo, &repo.odb, &path)?,
-                Err(e) => {
-                    debug!("{e}");
-                    Diff::default()
+            let dvcfiles = find_targets(&repo, targets, recursive)?;
+            let mut diff = Diff::default();
+            let git_targets = dvcfiles
+                .iter()
+                .filter(|dvcfile| is_dvcfile(dvcfile) && dvcfile.is_file());
+            match Repository::discover(&repo.root) {
+                Ok(git_repo) => {
+                    for dvcfile in git_targets {
+                        diff = diff.merge(status_git(&git_repo, &repo.odb, &rev, dvcfile)?);
+                    }
                 }
-            };
+                Err(e) => debug!("{e}"),
+            }
+
+            let outputs = collect_outputs(&dvcfiles)?;
+            let statuses = status_all(&repo.odb, state, &repo.root, threads, &outputs)?;
+            let mut diff = diff.detect_renames(rename_limit);
+            diff.fill_sizes(&repo.odb, Some(&cwd));
+            let statuses: Vec<_> = statuses
+                .into_iter()
+                .map(|st| {
+                    let mut diff = st.diff.detect_renames(rename_limit);
+                    diff.fill_sizes(&repo.odb, Some(&cwd));
+                    OutputStatus { diff, ..st }
+                })
+                .collect();
+
+            if let Some(depth) = summary {
+                let committed = relative_diff(diff, &cwd);
+                let uncommitted = statuses
+                    .into_iter()
+                    .fold(Diff::default(), |acc, st| acc.merge(st.diff));
+                let uncommitted = relative_diff(uncommitted, &cwd);
+                let diffs = [("committed", &committed), ("uncommitted", &uncommitted)];
+                print_summaries(&diffs, depth, json, md)?;
+                return Ok(());
+            }
+
+            if json || md {
+                let committed = relative_diff(diff, &cwd);
+                let uncommitted: Vec<_> = statuses
+                    .into_iter()
+                    .map(|st| OutputStatus {
+                        path: relative_to(&st.path, &cwd).to_path_buf(),
+                        diff: relative_diff(st.diff, &cwd),
+                        not_in_cache: st
+                            .not_in_cache
+                            .into_iter()
+                            .map(|(path, oid)| (relative_to(&path, &cwd).to_path_buf(), oid))
+                            .collect(),
+                        ..st
+                    })
+                    .collect();
+                if json {
+                    let report = serde_json::json!({
+                        "committed": committed,
+                        "uncommitted": uncommitted,
+                    });
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    let mut rows = Vec::new();
+                    for mut row in diff_rows(&committed) {
+                        row.insert(0, "committed".to_owned());
+                        rows.push(row);
+                    }
+                    for st in &uncommitted {
+                        if st.state != OutputState::UpToDate && st.diff.is_empty() {
+                            rows.push(vec![
+                                "uncommitted".to_owned(),
+                                state_label(st.state).to_owned(),
+                                st.path.to_string(),
+                                String::new(),
+                                String::new(),
+                            ]);
+                        }
+                        for mut row in diff_rows(&st.diff) {
+                            row.insert(0, "uncommitted".to_owned());
+                            rows.push(row);
+                        }
+                        for (path, oid) in &st.not_in_cache {
+                            rows.push(vec![
+                                "uncommitted".to_owned(),
+                                state_label(OutputState::NotInCache).to_owned(),
+                                path.to_string(),
+                                oid.to_string(),
+                                String::new(),
+                            ]);
+                        }
+                    }
+                    print_markdown_table(&["Stage", "Type", "Path", "Old", "New"], &rows);
+                }
+                return Ok(());
+            }
+
             let commit_diff = !diff.is_empty() && {
                 println!("DVC committed changes:");
-                for added in diff.added.keys() {
-                    let line = format!("{}: {}", "added", added.as_str());
-                    println!("\t{}", style(line).green());
-                }
-                for modified in diff.modified.keys() {
-                    let line = format!("{}: {}", "modified", modified.as_str());
-                    println!("\t{}", style(line).green());
-                }
-                for removed in diff.removed.keys() {
-                    let line = format!("{}: {}", "deleted", removed.as_str());
-                    println!("\t{}", style(line).green());
-                }
+                print_diff(&diff, &cwd, Color::Green);
                 true
             };
-
-            let diff = status(&repo.odb, state, &ignore, threads, &path)?;
-            if !diff.is_empty() {
+            if statuses.iter().any(|st| st.state != OutputState::UpToDate) {
                 if commit_diff {
                     println!();
                 }
                 println!("DVC uncommitted changes:");
-                for added in diff.added.keys() {
-                    let line = format!("{}: {}", "added", added.as_str());
-                    println!("\t{}", style(line).yellow());
-                }
-                for modified in diff.modified.keys() {
-                    let line = format!("{}: {}", "modified", modified.as_str());
-                    println!("\t{}", style(line).yellow());
-                }
-                for removed in diff.removed.keys() {
-                    let line = format!("{}: {}", "deleted", removed.as_str());
-                    println!("\t{}", style(line).yellow());
+                for st in &statuses {
+                    let path = relative_to(&st.path, &cwd);
+                    match st.state {
+                        OutputState::UpToDate | OutputState::NotInCache => (),
+                        OutputState::Modified => print_diff(&st.diff, &cwd, Color::Yellow),
+                        OutputState::Deleted => {
+                            let line = format!("{}: {path}", state_label(st.state));
+                            println!("\t{}", style(line).yellow());
+                        }
+                    }
+                    for path in st.not_in_cache.keys() {
+                        let path = relative_to(path, &cwd);
+                        let line = format!("{}: {path}", state_label(OutputState::NotInCache));
+                        println!("\t{}", style(line).red());
+                    }
                 }
             }
+            let missing: usize = statuses.iter().map(|st| st.not_in_cache.len()).sum();
+            if missing > 0 {
+                println!("\n{missing} object(s) not in cache, fetch them before checkout.");
+            }
             Ok(())
         }
     }
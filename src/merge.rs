@@ -0,0 +1,134 @@
+use crate::models::{DvcFile, Output, update_output};
+use crate::objects::{Object, Oid, Tree, TreeEntry, TreeError};
+use crate::odb::{Odb, oid_to_path};
+use crate::transfer::write_obj;
+use camino::Utf8PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum MergeError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeYaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    TreeError(#[from] TreeError),
+    #[error("both sides changed the output '{0}' and it is not a directory")]
+    NotATree(Utf8PathBuf),
+    #[error("both sides changed the output path: '{0}' and '{1}'")]
+    PathConflict(Utf8PathBuf, Utf8PathBuf),
+    #[error("conflicting changes to {count} path(s) in '{path}'", path = .0, count = .1.len())]
+    Conflict(Utf8PathBuf, Vec<Utf8PathBuf>),
+}
+
+/// Three-way merges tree entries by relpath.
+///
+/// A path changed, added or removed on one side only takes that side's
+/// version. Paths changed differently on both sides are returned as conflicts.
+pub fn merge_trees(base: &Tree, ours: &Tree, theirs: &Tree) -> Result<Tree, Vec<Utf8PathBuf>> {
+    let by_path = |tree: &Tree| -> BTreeMap<Utf8PathBuf, Oid> {
+        tree.iter()
+            .map(|entry| (entry.relpath, entry.oid))
+            .collect()
+    };
+    let (base, ours, theirs) = (by_path(base), by_path(ours), by_path(theirs));
+    let paths: BTreeSet<_> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut conflicts = Vec::new();
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let merged = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            conflicts.push(path.clone());
+            continue;
+        };
+        if let Some(oid) = merged {
+            entries.push(TreeEntry {
+                relpath: path.clone(),
+                oid: *oid,
+            });
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(Tree::from_entries(entries).expect("merged entries come from valid trees"))
+    } else {
+        Err(conflicts)
+    }
+}
+
+fn load_tree(odb: &Odb, output: Option<&Output>) -> Result<Tree, MergeError> {
+    match output {
+        Some(out) if out.oid.is_tree() => match odb.load_object(&out.oid)? {
+            Object::Tree(tree) => Ok(tree),
+            Object::HashFile(_) => Ok(Tree::default()),
+        },
+        _ => Ok(Tree::default()),
+    }
+}
+
+/// Three-way merges the contents of `.dvc` files, e.g. as a git merge driver.
+///
+/// If both sides changed a directory output, its trees are merged and the
+/// merged `.dir` object is written to `odb`. `base` is empty if the file was
+/// added on both sides.
+pub fn merge_dvcfiles(
+    odb: &Odb,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+) -> Result<String, MergeError> {
+    let base = if base.trim().is_empty() {
+        None
+    } else {
+        Some(serde_yaml::from_str::<DvcFile>(base)?.outs.0)
+    };
+    let ours_out = serde_yaml::from_str::<DvcFile>(ours)?.outs.0;
+    let theirs_out = serde_yaml::from_str::<DvcFile>(theirs)?.outs.0;
+
+    let base_oid = base.as_ref().map(|out| &out.oid);
+    if ours_out.oid == theirs_out.oid || base_oid == Some(&theirs_out.oid) {
+        return Ok(ours.to_owned());
+    }
+    if base_oid == Some(&ours_out.oid) {
+        return Ok(theirs.to_owned());
+    }
+    if ours_out.path != theirs_out.path {
+        return Err(MergeError::PathConflict(ours_out.path, theirs_out.path));
+    }
+    if !ours_out.oid.is_tree() || !theirs_out.oid.is_tree() {
+        return Err(MergeError::NotATree(ours_out.path));
+    }
+
+    let tree = merge_trees(
+        &load_tree(odb, base.as_ref())?,
+        &load_tree(odb, Some(&ours_out))?,
+        &load_tree(odb, Some(&theirs_out))?,
+    )
+    .map_err(|conflicts| MergeError::Conflict(ours_out.path.clone(), conflicts))?;
+    let (serialized, oid) = tree.digest()?;
+    write_obj(&odb.path, &oid, &serialized)?;
+
+    // the size is only known if every object is in the cache
+    let size = tree
+        .iter()
+        .map(|entry| fs::metadata(oid_to_path(&odb.path, &entry.oid)).map(|meta| meta.len()))
+        .sum::<std::io::Result<u64>>()
+        .ok();
+    Ok(update_output(
+        ours,
+        &ours_out.path,
+        oid,
+        size,
+        Some(tree.len()),
+    )?)
+}
@@ -1,5 +1,8 @@
+use crate::objects::Oid;
 use camino::{FromPathError, Utf8Path, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
@@ -8,7 +11,7 @@ use thiserror::Error;
 pub struct Output {
     pub hash: String,
     #[serde(rename = "md5")]
-    pub oid: String,
+    pub oid: Oid,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub size: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -21,6 +24,18 @@ pub struct DvcFile {
     pub outs: (Output,),
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LockStage {
+    #[serde(default)]
+    pub outs: Vec<Output>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub stages: BTreeMap<String, LockStage>,
+}
+
 #[derive(Error, Debug)]
 pub enum DvcFileCreateError {
     #[error("failed to validate contents")]
@@ -32,6 +47,27 @@ pub enum DvcFileCreateError {
 }
 
 const DVCFILE_EXT: &str = "dvc";
+pub const LOCKFILE_NAME: &str = "dvc.lock";
+
+pub fn is_dvcfile(path: &Utf8Path) -> bool {
+    path.file_name() == Some(LOCKFILE_NAME) || path.extension() == Some(DVCFILE_EXT)
+}
+
+/// Parses the outputs recorded in a `.dvc` file or `dvc.lock`, depending on
+/// the name of `dvcfile`.
+pub fn parse_outputs(dvcfile: &Utf8Path, contents: &str) -> Result<Vec<Output>, serde_yaml::Error> {
+    if dvcfile.file_name() == Some(LOCKFILE_NAME) {
+        let lockfile: Lockfile = serde_yaml::from_str(contents)?;
+        Ok(lockfile
+            .stages
+            .into_values()
+            .flat_map(|stage| stage.outs)
+            .collect())
+    } else {
+        let dvcfile_obj: DvcFile = serde_yaml::from_str(contents)?;
+        Ok(vec![dvcfile_obj.outs.0])
+    }
+}
 
 pub fn default_dvcfile_path(path: &Utf8Path) -> Utf8PathBuf {
     match path.extension() {
@@ -61,7 +97,7 @@ impl DvcFile {
     pub fn create(
         dvcfile: &Path,
         path: &Utf8Path,
-        oid: String,
+        oid: Oid,
         size: Option<u64>,
         nfiles: Option<usize>,
     ) -> Result<(), DvcFileCreateError> {
@@ -73,11 +109,57 @@ impl DvcFile {
             path: path.to_path_buf(),
         };
         let dvcfile_obj = Self { outs: (output,) };
-        let contents = serde_yaml::to_string(&dvcfile_obj)?;
-        let processed = contents
-            .strip_prefix("---")
-            .unwrap_or(&contents)
-            .trim_start();
-        Ok(fs::write(dvcfile, processed)?)
+        Ok(fs::write(dvcfile, dvcfile_obj.dump()?)?)
     }
+
+    /// Serializes to the contents of a `.dvc` file.
+    pub fn dump(&self) -> Result<String, serde_yaml::Error> {
+        to_yaml(self)
+    }
+}
+
+fn to_yaml<T: Serialize>(value: &T) -> Result<String, serde_yaml::Error> {
+    let contents = serde_yaml::to_string(value)?;
+    Ok(contents
+        .strip_prefix("---")
+        .unwrap_or(&contents)
+        .trim_start()
+        .to_owned())
+}
+
+/// Records a new `oid`, `size` and `nfiles` for the output at `path` in the
+/// contents of a `.dvc` file or `dvc.lock`. Every other key, including ones
+/// this crate does not know about, is kept as is.
+pub fn update_output(
+    contents: &str,
+    path: &Utf8Path,
+    oid: Oid,
+    size: Option<u64>,
+    nfiles: Option<usize>,
+) -> Result<String, serde_yaml::Error> {
+    let mut doc: Value = serde_yaml::from_str(contents)?;
+    let stages = match doc.get_mut("stages").and_then(Value::as_mapping_mut) {
+        Some(stages) => stages.values_mut().collect(),
+        None => vec![&mut doc],
+    };
+    let output = stages
+        .into_iter()
+        .filter_map(|stage| stage.get_mut("outs").and_then(Value::as_sequence_mut))
+        .flatten()
+        .filter_map(Value::as_mapping_mut)
+        .find(|out| out.get("path").and_then(Value::as_str) == Some(path.as_str()))
+        .ok_or_else(|| {
+            <serde_yaml::Error as serde::de::Error>::custom(format!("no output '{path}'"))
+        })?;
+
+    output.insert("md5".into(), oid.to_string().into());
+    match size {
+        Some(size) => output.insert("size".into(), size.into()),
+        None => output.shift_remove("size"),
+    };
+    match nfiles {
+        Some(nfiles) => output.insert("nfiles".into(), (nfiles as u64).into()),
+        None => output.shift_remove("nfiles"),
+    };
+    to_yaml(&doc)
 }
@@ -1,12 +1,22 @@
-use crate::hash::md5;
+use crate::hash::md5_digest;
 use crate::json_format;
-use camino::{Utf8Path, Utf8PathBuf};
-use serde::{Deserialize, Serialize, Serializer};
+pub use crate::oid::Oid;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use rayon::prelude::*;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::convert::From;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::sync::Arc;
 use thiserror::Error as ThisError;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
 
 #[derive(ThisError, Debug)]
 pub enum TreeError {
@@ -14,6 +24,32 @@ pub enum TreeError {
     Io(#[from] std::io::Error),
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("tree is too large")]
+    TooLarge,
+    #[error("invalid tree entry '{0}': {1}")]
+    InvalidEntry(Utf8PathBuf, InvalidEntry),
+}
+
+/// Why an entry was rejected from a `Tree`, see `TreeError::InvalidEntry`.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidEntry {
+    #[error("relpath must be relative and must not contain '..' or '.'")]
+    UnsafeRelpath,
+    #[error("duplicate relpath")]
+    Duplicate,
+    #[error("entries are not sorted by relpath")]
+    Unsorted,
+}
+
+/// Whether `relpath` stays inside the directory it is joined onto.
+fn is_safe_relpath(relpath: &Utf8Path) -> bool {
+    relpath
+        .as_str()
+        .split('/')
+        .all(|part| !matches!(part, "" | "." | ".."))
+        && relpath
+            .components()
+            .all(|component| matches!(component, Utf8Component::Normal(_)))
 }
 
 #[derive(Debug, Clone)]
@@ -22,16 +58,29 @@ pub enum Object {
     HashFile(HashFile),
 }
 
-pub type HashFile = String;
-pub type Oid = String;
+pub type HashFile = Oid;
 
-#[derive(Deserialize, Clone, PartialEq, Debug, PartialOrd, Ord, Eq)]
+#[derive(Deserialize, Clone, PartialEq, Debug, Eq)]
 pub struct TreeEntry {
     pub relpath: Utf8PathBuf,
     #[serde(rename = "md5")]
     pub oid: Oid,
 }
 
+/// Orders by relpath as a string, like DVC sorts `.dir` entries, so `a.txt`
+/// sorts before `a/b`.
+impl Ord for TreeEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.relpath.as_str(), self.oid).cmp(&(other.relpath.as_str(), other.oid))
+    }
+}
+
+impl PartialOrd for TreeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// private helper for ordering `TreeEntry` on serialization, keep `md5` before relpath
 #[derive(Serialize, Debug)]
 struct TreeEntrySerializer {
@@ -44,18 +93,12 @@ struct TreeEntrySerializer {
 impl From<&TreeEntry> for TreeEntrySerializer {
     fn from(value: &TreeEntry) -> Self {
         Self {
-            oid: value.oid.clone(),
+            oid: value.oid,
             relpath: value.relpath.clone(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
-#[serde(transparent)]
-pub struct Tree {
-    pub entries: Vec<TreeEntry>,
-}
-
 impl Serialize for TreeEntry {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -73,21 +116,381 @@ where
     s.serialize_str(&parts.join("/"))
 }
 
+/// Normalization of relpaths written on other platforms, applied when
+/// loading and building trees so they compare equal to locally built ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Normalization {
+    /// Treat `\` as a separator, as in relpaths written on Windows.
+    pub backslashes: bool,
+    /// Compose relpaths to Unicode NFC, as macOS may store names decomposed.
+    pub nfc: bool,
+}
+
+impl Normalization {
+    fn changes(self, s: &str) -> bool {
+        (self.backslashes && s.contains('\\')) || (self.nfc && !is_nfc(s))
+    }
+
+    pub fn relpath(self, relpath: &Utf8Path) -> Cow<'_, Utf8Path> {
+        if !self.changes(relpath.as_str()) {
+            return Cow::Borrowed(relpath);
+        }
+        let mut path = relpath.as_str().to_owned();
+        if self.backslashes {
+            path = path.replace('\\', "/");
+        }
+        if self.nfc {
+            path = path.nfc().collect();
+        }
+        Cow::Owned(path.into())
+    }
+}
+
+/// A `TreeEntry` as stored in `Tree`: an interned parent directory and a file
+/// name in `Tree::names`.
+#[derive(Debug, Clone, Copy)]
+struct PackedEntry {
+    dir: u32,
+    name_start: u32,
+    name_len: u32,
+    oid: Oid,
+}
+
+/// Files in a directory object, sorted by relpath as a string.
+///
+/// Entries are packed to keep trees with millions of files small, and
+/// `TreeEntry`s are only created when iterating.
+#[derive(Default, Clone)]
+pub struct Tree {
+    dirs: Vec<Arc<str>>,
+    dir_ids: HashMap<Arc<str>, u32>,
+    names: String,
+    entries: Vec<PackedEntry>,
+}
+
 impl Tree {
+    /// Builds a tree from `entries` in any order.
+    pub fn from_entries(entries: impl IntoIterator<Item = TreeEntry>) -> Result<Self, TreeError> {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.par_sort_unstable(); // sort keys, see `TreeEntry::cmp`
+        let mut tree = Self::default();
+        for entry in entries {
+            tree.push(&entry)?;
+        }
+        Ok(tree)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = TreeEntry> + '_ {
+        self.entries.iter().map(|entry| self.unpack(entry))
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = TreeEntry> + '_ {
+        self.entries.par_iter().map(|entry| self.unpack(entry))
+    }
+
+    fn intern_dir(&mut self, dir: &str) -> Result<u32, TreeError> {
+        if let Some(&id) = self.dir_ids.get(dir) {
+            return Ok(id);
+        }
+        let id = u32::try_from(self.dirs.len()).map_err(|_| TreeError::TooLarge)?;
+        let dir: Arc<str> = Arc::from(dir);
+        self.dirs.push(dir.clone());
+        self.dir_ids.insert(dir, id);
+        Ok(id)
+    }
+
+    fn pack(&mut self, entry: &TreeEntry) -> Result<PackedEntry, TreeError> {
+        if !is_safe_relpath(&entry.relpath) {
+            return Err(TreeError::InvalidEntry(
+                entry.relpath.clone(),
+                InvalidEntry::UnsafeRelpath,
+            ));
+        }
+        let dir = entry.relpath.parent().map_or("", Utf8Path::as_str);
+        let name = entry.relpath.file_name().unwrap_or_default();
+        let name_start = u32::try_from(self.names.len()).map_err(|_| TreeError::TooLarge)?;
+        let name_len = u32::try_from(name.len()).map_err(|_| TreeError::TooLarge)?;
+        self.names.push_str(name);
+        Ok(PackedEntry {
+            dir: self.intern_dir(dir)?,
+            name_start,
+            name_len,
+            oid: entry.oid,
+        })
+    }
+
+    fn name(&self, entry: &PackedEntry) -> &str {
+        let start = entry.name_start as usize;
+        &self.names[start..start + entry.name_len as usize]
+    }
+
+    fn unpack(&self, entry: &PackedEntry) -> TreeEntry {
+        TreeEntry {
+            relpath: Utf8Path::new(&*self.dirs[entry.dir as usize]).join(self.name(entry)),
+            oid: entry.oid,
+        }
+    }
+
+    /// Compares the relpath of `entry` with `relpath` byte by byte, as
+    /// entries are sorted, without allocating.
+    fn cmp_relpath(&self, entry: &PackedEntry, relpath: &Utf8Path) -> Ordering {
+        let dir = &*self.dirs[entry.dir as usize];
+        let sep = if dir.is_empty() { "" } else { "/" };
+        dir.bytes()
+            .chain(sep.bytes())
+            .chain(self.name(entry).bytes())
+            .cmp(relpath.as_str().bytes())
+    }
+
+    /// Appends an entry, which must sort after every entry in the tree.
+    fn push(&mut self, entry: &TreeEntry) -> Result<(), TreeError> {
+        if let Some(last) = self.entries.last() {
+            let invalid = match self.cmp_relpath(last, &entry.relpath) {
+                Ordering::Less => None,
+                Ordering::Equal => Some(InvalidEntry::Duplicate),
+                Ordering::Greater => Some(InvalidEntry::Unsorted),
+            };
+            if let Some(invalid) = invalid {
+                return Err(TreeError::InvalidEntry(entry.relpath.clone(), invalid));
+            }
+        }
+        let packed = self.pack(entry)?;
+        self.entries.push(packed);
+        Ok(())
+    }
+
+    /// Index of the entry at `relpath`, or where it would be inserted.
+    fn position(&self, relpath: &Utf8Path) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| self.cmp_relpath(entry, relpath))
+    }
+
     pub fn serialize(&self) -> Result<String, TreeError> {
-        // make it compatible with `json.dumps()` separator
+        // byte for byte the same as `json.dumps()` in DVC, so digests match
         Ok(json_format::to_string(self)?)
     }
 
-    pub fn digest(&self) -> Result<(String, String), TreeError> {
+    pub fn digest(&self) -> Result<(String, Oid), TreeError> {
         let serialized = self.serialize()?;
-        let reader = serialized.as_bytes().to_owned();
-        Ok((serialized, md5(&mut reader.as_slice()) + ".dir"))
+        let oid = Oid::md5(md5_digest(&mut serialized.as_bytes())).to_tree();
+        Ok((serialized, oid))
+    }
+
+    /// Inserts an entry, keeping entries sorted, and returns the oid it
+    /// replaced if `relpath` was already in the tree.
+    pub fn insert(&mut self, relpath: Utf8PathBuf, oid: Oid) -> Result<Option<Oid>, TreeError> {
+        let position = self.position(&relpath);
+        let packed = self.pack(&TreeEntry { relpath, oid })?;
+        match position {
+            Ok(i) => {
+                let old = std::mem::replace(&mut self.entries[i], packed);
+                Ok(Some(old.oid))
+            }
+            Err(i) => {
+                self.entries.insert(i, packed);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Removes the entry at `relpath` and returns its oid.
+    pub fn remove(&mut self, relpath: &Utf8Path) -> Option<Oid> {
+        let i = self.position(relpath).ok()?;
+        Some(self.entries.remove(i).oid)
+    }
+
+    /// Removes the entry at `relpath` and every entry under it, if it is a
+    /// directory.
+    pub fn remove_dir(&mut self, relpath: &Utf8Path) -> Vec<TreeEntry> {
+        if relpath.as_str().is_empty() {
+            let removed = self.iter().collect();
+            self.entries.clear();
+            return removed;
+        }
+        let mut removed: Vec<_> = self.remove(relpath).map_or_else(Vec::new, |oid| {
+            vec![TreeEntry {
+                relpath: relpath.to_path_buf(),
+                oid,
+            }]
+        });
+        // entries under `relpath` share the `relpath/` prefix, so they are
+        // contiguous, but `relpath.txt` may sort between them and `relpath`
+        let prefix = format!("{relpath}/");
+        let start = self.position(Utf8Path::new(&prefix)).unwrap_or_else(|i| i);
+        let len = self.entries[start..]
+            .iter()
+            .take_while(|entry| self.is_under(entry, relpath.as_str()))
+            .count();
+        let drained: Vec<_> = self.entries.drain(start..start + len).collect();
+        removed.extend(drained.iter().map(|entry| self.unpack(entry)));
+        removed
+    }
+
+    /// Replaces whatever is recorded at `relpath` with `obj`, or only removes
+    /// it if `obj` is `None`. Besides the entries `remove_dir` removes, a
+    /// parent of `relpath` recorded as a file is removed before inserting, as
+    /// it is a directory now. Returns the removed entries.
+    pub fn replace_path(
+        &mut self,
+        relpath: &Utf8Path,
+        obj: Option<&Object>,
+    ) -> Result<Vec<TreeEntry>, TreeError> {
+        let mut removed = self.remove_dir(relpath);
+        let Some(obj) = obj else {
+            return Ok(removed);
+        };
+        for parent in relpath.ancestors().skip(1) {
+            if let Some(oid) = self.remove(parent) {
+                removed.push(TreeEntry {
+                    relpath: parent.to_path_buf(),
+                    oid,
+                });
+            }
+        }
+        match obj {
+            Object::HashFile(oid) => {
+                self.insert(relpath.to_path_buf(), *oid)?;
+            }
+            Object::Tree(tree) => {
+                for entry in tree.iter() {
+                    self.insert(relpath.join(entry.relpath), entry.oid)?;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Whether `entry` is in the directory `dir` or one of its subdirectories.
+    fn is_under(&self, entry: &PackedEntry, dir: &str) -> bool {
+        self.dirs[entry.dir as usize]
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Applies `normalization` to every relpath. Entries that end up with the
+    /// same relpath are rejected as duplicates.
+    pub fn normalize(self, normalization: Normalization) -> Result<Self, TreeError> {
+        if !normalization.changes(&self.names)
+            && !self.dirs.iter().any(|dir| normalization.changes(dir))
+        {
+            return Ok(self);
+        }
+        Self::from_entries(self.iter().map(|entry| TreeEntry {
+            relpath: normalization.relpath(&entry.relpath).into_owned(),
+            oid: entry.oid,
+        }))
+    }
+
+    /// Groups of relpaths that only differ in case, which overwrite each
+    /// other when checked out on a case-insensitive filesystem.
+    pub fn case_collisions(&self) -> Vec<Vec<Utf8PathBuf>> {
+        let mut by_key: HashMap<String, Vec<Utf8PathBuf>> = HashMap::new();
+        for entry in self.iter() {
+            let key = entry.relpath.as_str().to_lowercase().nfc().collect();
+            by_key.entry(key).or_default().push(entry.relpath);
+        }
+        let mut collisions: Vec<_> = by_key
+            .into_values()
+            .filter(|relpaths| relpaths.len() > 1)
+            .collect();
+        collisions.sort_unstable();
+        collisions
     }
 
+    /// Loads a `.dir` object, parsing one entry at a time.
+    ///
+    /// Entries must be sorted, unique and stay inside the tree, as their
+    /// relpaths are joined onto the checkout directory.
     pub fn load_from(path: &PathBuf) -> Result<Self, TreeError> {
         let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        Ok(serde_json::from_reader(reader)?)
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+        let mut invalid = None;
+        let tree = TreeSeed(&mut invalid)
+            .deserialize(&mut deserializer)
+            .map_err(|e| invalid.take().unwrap_or(TreeError::Json(e)))?;
+        deserializer.end()?;
+        Ok(tree)
+    }
+}
+
+impl PartialEq for Tree {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Tree {}
+
+impl fmt::Debug for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for Tree {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for entry in self.iter() {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+/// Deserializes a `Tree`, keeping the `TreeError` of a rejected entry, which
+/// serde can only report as a message.
+struct TreeSeed<'a>(&'a mut Option<TreeError>);
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_> {
+    type Value = Tree;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Tree, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TreeSeed<'_> {
+    type Value = Tree;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of tree entries")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Tree, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut tree = Tree::default();
+        while let Some(entry) = seq.next_element::<TreeEntry>()? {
+            if let Err(e) = tree.push(&entry) {
+                let error = serde::de::Error::custom(&e);
+                *self.0 = Some(e);
+                return Err(error);
+            }
+        }
+        Ok(tree)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tree {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TreeSeed(&mut None).deserialize(deserializer)
     }
 }
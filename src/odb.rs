@@ -1,26 +1,78 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use std::{fs, io};
 
+use rayon::prelude::*;
+
+use crate::objects::{Normalization, Oid};
 use crate::{Object, Tree, objects::TreeError};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Odb {
     pub path: PathBuf,
+    /// Applied to the relpaths of trees loaded from, or built for, this odb.
+    pub normalization: Normalization,
 }
 
-pub fn oid_to_path(root: &Path, oid: &str) -> PathBuf {
-    let mut to = root.join(&oid[..2]);
-    to.push(&oid[2..]);
+pub fn oid_to_path(root: &Path, oid: &Oid) -> PathBuf {
+    let name = oid.to_string();
+    let mut to = root.join(&name[..2]);
+    to.push(&name[2..]);
     to
 }
 
+/// The inverse of [`oid_to_path`]: the file object that `path` names under
+/// `root`, if any. Both paths should be canonical.
+pub fn path_to_oid(root: &Path, path: &Path) -> Option<Oid> {
+    let mut parts = path.strip_prefix(root).ok()?.iter();
+    let (Some(prefix), Some(name), None) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    let oid: Oid = format!("{}{}", prefix.to_str()?, name.to_str()?)
+        .parse()
+        .ok()?;
+    (!oid.is_tree()).then_some(oid)
+}
+
 impl Odb {
-    pub fn load_object(&self, oid: &str) -> Result<Object, TreeError> {
-        if oid.ends_with(".dir") {
+    pub fn load_object(&self, oid: &Oid) -> Result<Object, TreeError> {
+        if oid.is_tree() {
             let path = oid_to_path(&self.path, oid);
-            let tree = Tree::load_from(&path)?;
+            let tree = Tree::load_from(&path)?.normalize(self.normalization)?;
             Ok(Object::Tree(tree))
         } else {
-            Ok(Object::HashFile(oid.to_string()))
+            Ok(Object::HashFile(*oid))
+        }
+    }
+
+    /// Returns the subset of `oids` present in this odb. Each prefix
+    /// directory is listed once, instead of checking every object on its own.
+    pub fn existing<'a>(
+        &self,
+        oids: impl IntoIterator<Item = &'a Oid>,
+    ) -> io::Result<HashSet<Oid>> {
+        let mut by_prefix: BTreeMap<u8, Vec<&Oid>> = BTreeMap::new();
+        for oid in oids {
+            by_prefix.entry(oid.digest()[0]).or_default().push(oid);
         }
+        let found = by_prefix
+            .into_par_iter()
+            .map(|(prefix, oids)| {
+                let names = match fs::read_dir(self.path.join(format!("{prefix:02x}"))) {
+                    Ok(entries) => entries
+                        .map(|entry| Ok(entry?.file_name()))
+                        .collect::<io::Result<HashSet<_>>>()?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+                    Err(e) => return Err(e),
+                };
+                Ok(oids
+                    .into_iter()
+                    .filter(|oid| names.contains(OsStr::new(&oid.to_string()[2..])))
+                    .copied()
+                    .collect::<Vec<_>>())
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(found.into_iter().flatten().collect())
     }
 }
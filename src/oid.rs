@@ -0,0 +1,132 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error as ThisError;
+
+const TREE_SUFFIX: &str = ".dir";
+
+#[derive(ThisError, Debug, PartialEq, Eq)]
+pub enum OidError {
+    #[error("invalid md5 '{0}', expected 32 lowercase hex digits")]
+    InvalidMd5(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashAlgorithm {
+    #[default]
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+        }
+    }
+}
+
+/// Id of an object in the cache: a digest of its contents, and whether it is
+/// a directory (`.dir`) object listing a tree.
+///
+/// Formats as the hex digest with a `.dir` suffix for trees, as in `.dvc` files.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Oid {
+    algorithm: HashAlgorithm,
+    digest: [u8; 16],
+    is_tree: bool,
+}
+
+impl Oid {
+    /// Oid of a file with the given md5 digest.
+    pub fn md5(digest: [u8; 16]) -> Self {
+        Self {
+            algorithm: HashAlgorithm::Md5,
+            digest,
+            is_tree: false,
+        }
+    }
+
+    /// The same digest, as the oid of a directory object.
+    #[must_use]
+    pub fn to_tree(self) -> Self {
+        Self {
+            is_tree: true,
+            ..self
+        }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn digest(&self) -> &[u8; 16] {
+        &self.digest
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.is_tree
+    }
+
+    /// The digest in hex, without the `.dir` suffix.
+    pub fn hex(&self) -> String {
+        hex::encode(self.digest)
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.hex())?;
+        if self.is_tree {
+            f.write_str(TREE_SUFFIX)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Oid({self})")
+    }
+}
+
+impl FromStr for Oid {
+    type Err = OidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hex, is_tree) = match s.strip_suffix(TREE_SUFFIX) {
+            Some(hex) => (hex, true),
+            None => (s, false),
+        };
+        let mut digest = [0; 16];
+        // only lowercase, so that the oid formats back to the same string
+        if !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+            || hex::decode_to_slice(hex, &mut digest).is_err()
+        {
+            return Err(OidError::InvalidMd5(s.to_owned()));
+        }
+        Ok(Self {
+            algorithm: HashAlgorithm::Md5,
+            digest,
+            is_tree,
+        })
+    }
+}
+
+impl Serialize for Oid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Oid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
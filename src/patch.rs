@@ -0,0 +1,106 @@
+use crate::objects::Oid;
+use crate::odb::{Odb, oid_to_path};
+use camino::Utf8Path;
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::path::Path;
+
+/// Objects larger than this are not diffed line by line.
+pub const MAX_PATCH_SIZE: u64 = 1024 * 1024;
+
+const TEXT_EXTENSIONS: [&str; 6] = ["csv", "tsv", "json", "yaml", "yml", "txt"];
+
+/// Whether `path` looks like a text file whose contents are worth diffing.
+pub fn is_text_path(path: &Utf8Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn read_small_text(path: &Path) -> Option<String> {
+    let meta = fs::metadata(path).ok()?;
+    if !meta.is_file() || meta.len() > MAX_PATCH_SIZE {
+        return None;
+    }
+    String::from_utf8(fs::read(path).ok()?).ok()
+}
+
+/// Reads the object `oid` as text, falling back to `workspace_path` if the
+/// object is not in the cache. Returns `None` for large or non-UTF-8 files.
+pub fn read_text(odb: &Odb, oid: &Oid, workspace_path: Option<&Path>) -> Option<String> {
+    read_small_text(&oid_to_path(&odb.path, oid)).or_else(|| read_small_text(workspace_path?))
+}
+
+/// Unified diff of `old` and `new`, with `path` in the `---`/`+++` headers.
+pub fn unified_diff(path: &Utf8Path, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string()
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TableSummary {
+    pub rows_added: usize,
+    pub rows_removed: usize,
+    pub columns_added: Vec<String>,
+    pub columns_removed: Vec<String>,
+}
+
+/// Splits a delimited line into fields, honouring double-quoted fields.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Summarizes changes between two versions of a CSV or TSV file, with the
+/// first line taken as the header. Rows are compared line by line, so a
+/// changed row counts as one removed and one added row.
+pub fn table_summary(path: &Utf8Path, old: &str, new: &str) -> TableSummary {
+    let delimiter = if path.extension() == Some("tsv") {
+        '\t'
+    } else {
+        ','
+    };
+    let (old_header, old_rows) = old.split_once('\n').unwrap_or((old, ""));
+    let (new_header, new_rows) = new.split_once('\n').unwrap_or((new, ""));
+    let old_columns = split_fields(old_header, delimiter);
+    let new_columns = split_fields(new_header, delimiter);
+
+    let mut summary = TableSummary {
+        columns_added: new_columns
+            .iter()
+            .filter(|c| !old_columns.contains(c))
+            .cloned()
+            .collect(),
+        columns_removed: old_columns
+            .iter()
+            .filter(|c| !new_columns.contains(c))
+            .cloned()
+            .collect(),
+        ..TableSummary::default()
+    };
+    for change in TextDiff::from_lines(old_rows, new_rows).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => summary.rows_added += 1,
+            ChangeTag::Delete => summary.rows_removed += 1,
+            ChangeTag::Equal => (),
+        }
+    }
+    summary
+}
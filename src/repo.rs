@@ -22,6 +22,12 @@ pub enum RepoError {
     ConfigError(#[from] crate::config::ConfigError),
     #[error("Repository not found")]
     NotFound,
+    #[error("no remote given and no default remote is set in core.remote")]
+    NoDefaultRemote,
+    #[error("remote '{0}' does not exist")]
+    UnknownRemote(String),
+    #[error("remote url '{0}' is not supported, only local paths are")]
+    UnsupportedRemote(String),
 }
 
 #[derive(Debug)]
@@ -117,7 +123,10 @@ impl Repo {
         let state_path = db_dir.join("hashes/local/cache.db");
         let repo = Self {
             root,
-            odb: Odb { path: object_dir },
+            odb: Odb {
+                path: object_dir,
+                normalization: config.tree.normalization(),
+            },
             state: State::open(&state_path)?.instantiate()?,
             config,
         };
@@ -125,6 +134,28 @@ impl Repo {
         Ok(repo)
     }
 
+    /// Opens the object database of the remote `name`, or of `core.remote` if
+    /// not given. Relative urls are resolved from the `.dvc` directory.
+    pub fn remote_odb(&self, name: Option<&str>) -> Result<Odb, RepoError> {
+        let name = name
+            .or(self.config.core.remote.as_deref())
+            .ok_or(RepoError::NoDefaultRemote)?;
+        let remote = self
+            .config
+            .remotes
+            .get(name)
+            .ok_or_else(|| RepoError::UnknownRemote(name.to_owned()))?;
+        let url = remote.url.strip_prefix("file://").unwrap_or(&remote.url);
+        if url.contains("://") {
+            return Err(RepoError::UnsupportedRemote(remote.url.clone()));
+        }
+        let path = self.root.join(".dvc").join(url);
+        Ok(Odb {
+            path: path.join("files").join("md5"),
+            normalization: self.config.tree.normalization(),
+        })
+    }
+
     pub fn discover(path: Option<PathBuf>) -> Result<Self, RepoError> {
         let path = path.unwrap_or(env::current_dir()?);
         let path = fs::canonicalize(path)?;
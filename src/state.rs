@@ -6,15 +6,17 @@ use std::collections::HashMap;
 use std::fs;
 use std::iter::repeat_n;
 use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
 use std::time::SystemTime;
 use thiserror::Error as ThisError;
 
+use crate::objects::Oid;
 use crate::timeutils::unix_time;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct StateHash {
     #[serde(rename = "md5")]
-    pub oid: String,
+    pub oid: Oid,
 }
 
 const MODE_TEXT: u8 = 1;
@@ -28,7 +30,8 @@ pub struct StateValue {
 
 #[derive(Debug)]
 pub struct State {
-    conn: Connection,
+    // guarded so that a single `State` can be shared across the thread pool
+    conn: Mutex<Connection>,
 }
 
 #[derive(ThisError, Debug)]
@@ -47,13 +50,18 @@ impl State {
             fs::create_dir_all(parent)?;
         }
         Self {
-            conn: Connection::open(path)?,
+            conn: Mutex::new(Connection::open(path)?),
         }
         .instantiate()
     }
 
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().expect("state connection lock poisoned")
+    }
+
     pub fn instantiate(self) -> Result<Self, StateError> {
-        self.conn.execute(
+        let conn = self.conn();
+        conn.execute(
             "CREATE TABLE IF NOT EXISTS Cache (
                     rowid INTEGER PRIMARY KEY,
                     key BLOB,
@@ -70,26 +78,27 @@ impl State {
                 )",
             (),
         )?;
-        self.conn.execute(
+        conn.execute(
             "CREATE UNIQUE INDEX IF NOT EXISTS Cache_key_raw ON Cache(key, raw)",
             (),
         )?;
-        self.conn.pragma_update(None, "synchronous", "NORMAL")?;
-        self.conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        drop(conn);
         Ok(self)
     }
 
     pub fn open_in_memory() -> Result<Self, StateError> {
         Self {
-            conn: Connection::open_in_memory()?,
+            conn: Mutex::new(Connection::open_in_memory()?),
         }
         .instantiate()
     }
 
     pub fn get(&self, key: &str) -> Result<Option<StateValue>, StateError> {
-        let mut statement = self
-            .conn
-            .prepare_cached("SELECT value FROM Cache WHERE key = :key and raw = 1")?;
+        let conn = self.conn();
+        let mut statement =
+            conn.prepare_cached("SELECT value FROM Cache WHERE key = :key and raw = 1")?;
         let mut rows = statement.query_map(named_params! {":key": key}, |row| row.get("value"))?;
         if let Some(result) = rows.next() {
             let value: String = result?;
@@ -107,6 +116,7 @@ impl State {
     ) -> Result<HashMap<String, StateValue>, StateError> {
         let batch_size = batch_size.unwrap_or(7999);
         let mut res = HashMap::new();
+        let conn = self.conn();
 
         for chunk in &items.chunks(batch_size) {
             let chunk: Vec<_> = chunk.collect();
@@ -121,7 +131,7 @@ impl State {
                 + &params
                 + ")"
                 + " and raw = 1";
-            let mut statement = self.conn.prepare_cached(&query)?;
+            let mut statement = conn.prepare_cached(&query)?;
 
             let mut rows = statement.query(&*vector)?;
 
@@ -136,7 +146,8 @@ impl State {
     }
 
     pub fn set(&self, key: &str, value: &StateValue) -> Result<(), StateError> {
-        let mut statement = self.conn.prepare_cached(
+        let conn = self.conn();
+        let mut statement = conn.prepare_cached(
             "INSERT OR REPLACE INTO Cache(
             key, raw, store_time, expire_time, access_time, tag, mode, filename, value)
             VALUES (:key, :raw, :store_time, :expire_time, :access_time, :tag, :mode, :filename, :value)
@@ -168,7 +179,8 @@ impl State {
         }
 
         let time = unix_time(SystemTime::now());
-        let transaction = self.conn.unchecked_transaction()?;
+        let conn = self.conn();
+        let transaction = conn.unchecked_transaction()?;
 
         for chunk in &items.chunks(7999) {
             let chunk: Vec<_> = chunk.collect();
@@ -194,9 +206,8 @@ impl State {
     }
 
     pub fn is_empty(&self) -> Result<bool, StateError> {
-        let mut statement = self
-            .conn
-            .prepare_cached("SELECT EXISTS (SELECT 1 FROM Cache)")?;
+        let conn = self.conn();
+        let mut statement = conn.prepare_cached("SELECT EXISTS (SELECT 1 FROM Cache)")?;
 
         let mut rows = statement.query(())?;
         if let Some(row) = rows.next()? {
@@ -1,16 +1,22 @@
 use crate::Object;
-use crate::build::{BuildError, build};
-use crate::diff::{Diff, diff_object, diff_root};
-use crate::models::{DvcFile, Output, absolute_output_path};
-use crate::objects::TreeError;
-use crate::odb::Odb;
+use crate::build::{BuildError, BuildOptions, build};
+pub use crate::diff::diff_obj;
+use crate::diff::{Diff, Outputs, diff_root};
+use crate::git::{GitError, read_file, rev_tree, workdir_relpath};
+use crate::ignore::get_ignore;
+use crate::index::{IndexError, TrackedOutput, load_outputs};
+use crate::models::parse_outputs;
+use crate::objects::{Oid, TreeError};
+use crate::odb::{Odb, oid_to_path};
 use crate::state::State;
 use camino::{Utf8Path, Utf8PathBuf};
-use core::str;
+use git2::Repository;
 use ignore::gitignore::Gitignore;
-use std::path::Path;
-use std::path::PathBuf;
-use std::{env, fs};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use thiserror::Error as ThisError;
 
 #[derive(Debug, ThisError)]
@@ -29,88 +35,184 @@ pub enum StatusError {
     TreeError(#[from] TreeError),
     #[error(transparent)]
     StripPrefixError(#[from] std::path::StripPrefixError),
-    #[error("Bare git repository found at {0}")]
-    BareGitRepo(PathBuf),
+    #[error(transparent)]
+    IndexError(#[from] IndexError),
+    #[error(transparent)]
+    IgnoreError(#[from] ignore::Error),
+    #[error(transparent)]
+    GitError(#[from] GitError),
 }
 
-pub fn diff_obj(root: &Utf8Path, old: Option<Object>, new: Option<Object>) -> Diff {
-    let mut diff = Diff::default();
-    let granular_diff = diff_object(old, new);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputState {
+    UpToDate,
+    Modified,
+    Deleted,
+    NotInCache,
+}
 
-    for (path, key) in granular_diff.added {
-        diff.added.insert(root.join(path), key);
-    }
-    for (path, (old_key, new_key)) in granular_diff.modified {
-        diff.modified.insert(root.join(path), (old_key, new_key));
-    }
-    for (path, key) in granular_diff.removed {
-        diff.removed.insert(root.join(path), key);
-    }
-    for (path, key) in granular_diff.unchanged {
-        diff.unchanged.insert(root.join(path), key);
+#[derive(Debug, Serialize)]
+pub struct OutputStatus {
+    pub path: Utf8PathBuf,
+    pub state: OutputState,
+    /// Changes in the workspace relative to the recorded object.
+    #[serde(rename = "changes")]
+    pub diff: Diff,
+    /// Recorded files, or the output's tree itself, whose objects are missing
+    /// from the cache and need to be fetched before checkout.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub not_in_cache: BTreeMap<Utf8PathBuf, Oid>,
+}
+
+/// Finds the objects of `oid`, recorded for `path`, that are missing from the
+/// cache. A missing tree object is reported for `path` itself as its entries
+/// are unknown.
+pub fn missing_objects(
+    odb: &Odb,
+    path: &Utf8Path,
+    oid: &Oid,
+) -> Result<BTreeMap<Utf8PathBuf, Oid>, StatusError> {
+    let root = if oid.is_tree() {
+        path.join("")
+    } else {
+        path.to_path_buf()
+    };
+    if !oid_to_path(&odb.path, oid).is_file() {
+        return Ok(BTreeMap::from([(root, *oid)]));
     }
-    diff
+    let Object::Tree(tree) = odb.load_object(oid)? else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(tree
+        .par_iter()
+        .filter(|entry| !oid_to_path(&odb.path, &entry.oid).is_file())
+        .map(|entry| (root.join(entry.relpath), entry.oid))
+        .collect())
 }
 
-pub fn get_tree_obj(git_repo: &git2::Repository) -> Option<git2::Tree<'_>> {
-    git_repo.head().map_or(None, |r| {
-        let tree_id = r.peel_to_commit().expect("commit").tree_id();
-        Some(git_repo.find_tree(tree_id).expect("expected tree"))
-    })
+/// Loads `oid` from `odb`, or `None` for a tree object missing from the cache.
+fn load_cached(odb: &Odb, oid: &Oid) -> Result<Option<Object>, StatusError> {
+    if oid.is_tree() && !oid_to_path(&odb.path, oid).is_file() {
+        return Ok(None);
+    }
+    Ok(Some(odb.load_object(oid)?))
 }
 
-pub fn get_oid_for_path(tree: &git2::Tree, path: &Path) -> git2::Oid {
-    tree.get_path(path).expect("tree entry for file").id()
+/// Absolute path of `path`, which need not exist, without resolving `..` in
+/// its file name.
+fn absolute_path(path: &Utf8Path) -> Result<PathBuf, StatusError> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_str().is_empty() => parent.as_std_path(),
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default();
+    Ok(fs::canonicalize(parent)?.join(name))
 }
 
+/// Compares the outputs recorded in `dvcfile_path` with the ones recorded in
+/// the same dvcfile at the git revision `rev`.
+///
+/// A dvcfile that is not committed at `rev` yet, or a repository without any
+/// commits, reports all of its outputs as added. Granular changes are only
+/// reported for outputs whose tree objects are both in the cache.
 pub fn status_git(
-    git_repo: &git2::Repository,
+    git_repo: &Repository,
     odb: &Odb,
-    dvcfile_path: &Utf8PathBuf,
+    rev: &str,
+    dvcfile_path: &Utf8Path,
 ) -> Result<Diff, StatusError> {
-    let tree = get_tree_obj(git_repo);
-    let dvcfile_relpath = env::current_dir()?
-        .strip_prefix(
-            git_repo
-                .workdir()
-                .ok_or_else(|| StatusError::BareGitRepo(git_repo.path().to_path_buf()))?,
-        )?
-        .join(dvcfile_path);
-    let oid = match tree {
-        Some(t) => get_oid_for_path(&t, &dvcfile_relpath),
-        None => {
-            return Ok(Diff::default());
+    let tree = match rev_tree(git_repo, rev) {
+        Ok(tree) => Some(tree),
+        Err(_) if git_repo.is_empty().map_err(GitError::from)? => None,
+        Err(e) => return Err(e.into()),
+    };
+    let contents = match tree {
+        Some(tree) => {
+            let abspath = absolute_path(dvcfile_path)?;
+            let relpath = workdir_relpath(git_repo, &abspath)?;
+            read_file(git_repo, &tree, relpath)?
         }
+        None => None,
     };
-
-    let Ok(git_odb) = git_repo.odb() else {
-        return Ok(Diff::default());
+    let old = match contents {
+        Some(contents) => parse_outputs(dvcfile_path, &contents)
+            .map_err(|e| IndexError::InvalidDvcFile(dvcfile_path.to_path_buf(), e))?,
+        None => Vec::new(),
+    };
+    let mut old: BTreeMap<_, _> = old
+        .into_iter()
+        .map(|output| {
+            let out = TrackedOutput {
+                dvcfile: dvcfile_path.to_path_buf(),
+                output,
+            };
+            (out.path(), out.output.oid)
+        })
+        .collect();
+    let new: BTreeMap<_, _> = if dvcfile_path.is_file() {
+        load_outputs(dvcfile_path)?
+            .into_iter()
+            .map(|out| (out.path(), out.output.oid))
+            .collect()
+    } else {
+        BTreeMap::new()
     };
-    let git_obj = git_odb.read(oid).expect("object with oid");
-    let data = git_obj.data();
-
-    let contents = str::from_utf8(data).expect("Invalid utf8 sequence");
-
-    let dvcfile_obj: DvcFile = serde_yaml::from_str(contents)?;
-    let old_out = dvcfile_obj.outs.0;
-
-    let contents = &fs::read_to_string(dvcfile_path)?;
-    let dvcfile_obj: DvcFile = serde_yaml::from_str(contents)?;
-    let new_out = dvcfile_obj.outs.0;
 
-    let new_oid = new_out.oid;
-    let old_oid = old_out.oid;
+    let mut diff = Diff::default();
+    for (path, new_oid) in new {
+        let old_oid = old.remove(&path);
+        if old_oid.as_ref() == Some(&new_oid) {
+            continue;
+        }
+        let old_obj = old_oid
+            .as_ref()
+            .map(|oid| load_cached(odb, oid))
+            .transpose()?;
+        let new_obj = load_cached(odb, &new_oid)?;
+        match (old_obj, new_obj) {
+            (Some(None), _) | (_, None) => (),
+            (old_obj, Some(new_obj)) => {
+                diff = diff.merge(diff_obj(&path, old_obj.flatten(), Some(new_obj)));
+            }
+        }
+        diff = diff.merge(diff_root(&path, old_oid.as_ref(), Some(&new_oid)));
+    }
+    for (path, old_oid) in old {
+        if let Some(old_obj) = load_cached(odb, &old_oid)? {
+            diff = diff.merge(diff_obj(&path, Some(old_obj), None));
+        }
+        diff = diff.merge(diff_root(&path, Some(&old_oid), None));
+    }
+    Ok(diff)
+}
 
-    assert!(new_out.path == old_out.path);
+/// Compares the workspace at `path` with the recorded object `oid`.
+///
+/// Granular changes are only reported if the recorded object can be loaded
+/// from the cache.
+fn workspace_diff(
+    odb: &Odb,
+    state: Option<&State>,
+    ignore: &Gitignore,
+    jobs: usize,
+    path: &Utf8Path,
+    oid: &Oid,
+) -> Result<(Diff, Oid), StatusError> {
+    let (obj, _, _) = build(odb, path, state, ignore, jobs, &BuildOptions::default())?;
+    let obj_oid = match obj {
+        Object::Tree(ref t) => t.digest()?.1,
+        Object::HashFile(o) => o,
+    };
 
-    let old_obj = odb.load_object(&old_oid)?;
-    let new_obj = odb.load_object(&new_oid)?;
-    let path = match dvcfile_path.parent() {
-        Some(p) => p.join(new_out.path),
-        None => new_out.path,
+    let diff = if oid.is_tree() && !oid_to_path(&odb.path, oid).is_file() {
+        Diff::default()
+    } else {
+        let old_obj = odb.load_object(oid)?;
+        diff_obj(path, Some(old_obj), Some(obj))
     };
-    let diff = diff_obj(&path, Some(old_obj), Some(new_obj));
-    Ok(diff.merge(diff_root(&path, Some(&old_oid), Some(&new_oid))))
+    let diff = diff.merge(diff_root(path, Some(oid), Some(&obj_oid)));
+    Ok((diff, obj_oid))
 }
 
 pub fn status(
@@ -120,18 +222,157 @@ pub fn status(
     jobs: usize,
     dvcfile_path: &Utf8PathBuf,
 ) -> Result<Diff, StatusError> {
-    let contents = &fs::read_to_string(dvcfile_path)?;
-    let dvcfile_obj: DvcFile = serde_yaml::from_str(contents)?;
-    let Output { oid, path, .. } = dvcfile_obj.outs.0;
+    let mut diff = Diff::default();
+    for out in load_outputs(dvcfile_path)? {
+        let (out_diff, _) = workspace_diff(odb, state, ignore, jobs, &out.path(), &out.output.oid)?;
+        diff = diff.merge(out_diff);
+    }
+    Ok(diff)
+}
 
-    let path = absolute_output_path(dvcfile_path, &path);
-    let (obj, _) = build(odb, &path, state, ignore, jobs)?;
-    let obj_oid = match obj {
-        Object::Tree(ref t) => t.digest()?.1,
-        Object::HashFile(ref o) => o.clone(),
+/// Computes the status of a single tracked output.
+pub fn status_output(
+    odb: &Odb,
+    state: Option<&State>,
+    repo_root: &Path,
+    jobs: usize,
+    out: &TrackedOutput,
+) -> Result<OutputStatus, StatusError> {
+    let path = out.path();
+    let oid = &out.output.oid;
+    let not_in_cache = missing_objects(odb, &path, oid)?;
+    if fs::symlink_metadata(&path).is_err() {
+        return Ok(OutputStatus {
+            diff: diff_root(&path, Some(oid), None),
+            path,
+            state: OutputState::Deleted,
+            not_in_cache,
+        });
+    }
+
+    let parent = path.parent().unwrap_or(&path);
+    let ignore = get_ignore(repo_root, parent.as_std_path())?;
+    let (diff, obj_oid) = workspace_diff(odb, state, &ignore, jobs, &path, oid)?;
+    let state = if obj_oid != *oid {
+        OutputState::Modified
+    } else if not_in_cache.is_empty() {
+        OutputState::UpToDate
+    } else {
+        OutputState::NotInCache
     };
+    Ok(OutputStatus {
+        path,
+        state,
+        diff,
+        not_in_cache,
+    })
+}
+
+/// Builds the workspace objects of `outputs`, whose dvcfile paths are relative
+/// to `root`. Outputs missing from the workspace are left out.
+pub fn workspace_outputs(
+    odb: &Odb,
+    state: Option<&State>,
+    root: &Utf8Path,
+    jobs: usize,
+    outputs: &[TrackedOutput],
+) -> Result<Outputs, StatusError> {
+    let mut objects = Outputs::new();
+    for out in outputs {
+        let relpath = out.path();
+        let path = root.join(&relpath);
+        if fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        let parent = path.parent().unwrap_or(&path);
+        let ignore = get_ignore(root.as_std_path(), parent.as_std_path())?;
+        let (obj, _, _) = build(odb, &path, state, &ignore, jobs, &BuildOptions::default())?;
+        let oid = match obj {
+            Object::Tree(ref t) => t.digest()?.1,
+            Object::HashFile(o) => o,
+        };
+        objects.insert(relpath, (oid, Some(obj)));
+    }
+    Ok(objects)
+}
+
+/// Computes the status of every output in `outputs`, one after another,
+/// sharing `state` and the global thread pool between them.
+pub fn status_all(
+    odb: &Odb,
+    state: Option<&State>,
+    repo_root: &Path,
+    jobs: usize,
+    outputs: &[TrackedOutput],
+) -> Result<Vec<OutputStatus>, StatusError> {
+    outputs
+        .iter()
+        .map(|out| status_output(odb, state, repo_root, jobs, out))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudState {
+    Both,
+    LocalOnly,
+    RemoteOnly,
+    Missing,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CloudStatus {
+    pub path: Utf8PathBuf,
+    pub oid: Oid,
+    pub state: CloudState,
+}
+
+/// Compares the objects of `outputs`, including the entries of their trees,
+/// between the local cache `odb` and `remote`.
+///
+/// Trees are read from the local cache, or from the remote if they were not
+/// fetched. The entries of a tree that is in neither are not reported.
+pub fn cloud_status(
+    odb: &Odb,
+    remote: &Odb,
+    outputs: &[TrackedOutput],
+) -> Result<Vec<CloudStatus>, StatusError> {
+    let mut objects = BTreeMap::new();
+    for out in outputs {
+        let path = out.path();
+        let oid = &out.output.oid;
+        if !oid.is_tree() {
+            objects.insert(path, *oid);
+            continue;
+        }
+        objects.insert(path.join(""), *oid);
+        let tree = if oid_to_path(&odb.path, oid).is_file() {
+            odb.load_object(oid)?
+        } else if oid_to_path(&remote.path, oid).is_file() {
+            remote.load_object(oid)?
+        } else {
+            continue;
+        };
+        if let Object::Tree(tree) = tree {
+            objects.extend(
+                tree.iter()
+                    .map(|entry| (path.join(entry.relpath), entry.oid)),
+            );
+        }
+    }
 
-    let old_obj = odb.load_object(&oid)?;
-    let diff = diff_obj(&path, Some(old_obj), Some(obj));
-    Ok(diff.merge(diff_root(&path, Some(&oid), Some(&obj_oid))))
+    let local = odb.existing(objects.values())?;
+    let remote = remote.existing(objects.values())?;
+    Ok(objects
+        .into_iter()
+        .map(|(path, oid)| {
+            let state = match (local.contains(&oid), remote.contains(&oid)) {
+                (true, true) => CloudState::Both,
+                (true, false) => CloudState::LocalOnly,
+                (false, true) => CloudState::RemoteOnly,
+                (false, false) => CloudState::Missing,
+            };
+            CloudStatus { path, oid, state }
+        })
+        .collect())
 }
@@ -1,5 +1,6 @@
+use crate::build::Sources;
 use crate::fsutils::{protect_file, transfer_file};
-use crate::objects::{Object, Tree, TreeError};
+use crate::objects::{Object, Oid, Tree, TreeError};
 use crate::odb::{Odb, oid_to_path};
 use indicatif::{ParallelProgressIterator, ProgressBar};
 use rayon::prelude::*;
@@ -15,7 +16,7 @@ pub enum TransferError {
     Io(#[from] std::io::Error),
 }
 
-pub fn transfer_obj(root: &Path, from: &Path, oid: &str) -> std::io::Result<()> {
+pub fn transfer_obj(root: &Path, from: &Path, oid: &Oid) -> std::io::Result<()> {
     let to = oid_to_path(root, oid);
     if to.exists() {
         return Ok(());
@@ -25,7 +26,7 @@ pub fn transfer_obj(root: &Path, from: &Path, oid: &str) -> std::io::Result<()>
     Ok(())
 }
 
-pub fn write_obj(root: &Path, oid: &str, contents: &str) -> std::io::Result<()> {
+pub fn write_obj(root: &Path, oid: &Oid, contents: &str) -> std::io::Result<()> {
     let to = oid_to_path(root, oid);
     if to.exists() {
         return Ok(());
@@ -38,29 +39,41 @@ pub fn write_obj(root: &Path, oid: &str, contents: &str) -> std::io::Result<()>
     Ok(())
 }
 
-pub fn transfer_tree(odb: &Odb, wroot: &Path, tree: &Tree) -> Result<String, TransferError> {
-    let pb = ProgressBar::new(tree.entries.len() as u64);
+/// Transfers the files of `tree` under `wroot` to `odb`, reading the ones in
+/// `sources` from the path recorded there, see `BuildReport::sources`.
+pub fn transfer_tree(
+    odb: &Odb,
+    wroot: &Path,
+    tree: &Tree,
+    sources: &Sources,
+) -> Result<Oid, TransferError> {
+    let pb = ProgressBar::new(tree.len() as u64);
     fs::create_dir_all(&odb.path)?;
-    tree.entries
-        .par_iter()
-        .progress_with(pb)
-        .try_for_each(|entry| {
-            let file = wroot.join(&entry.relpath);
-            transfer_obj(&odb.path, &file, &entry.oid)?;
-            std::io::Result::Ok(())
-        })?;
+    tree.par_iter().progress_with(pb).try_for_each(|entry| {
+        let file = match sources.get(&entry.relpath) {
+            Some(source) => source.as_std_path().to_path_buf(),
+            None => wroot.join(&entry.relpath),
+        };
+        transfer_obj(&odb.path, &file, &entry.oid)?;
+        std::io::Result::Ok(())
+    })?;
 
     let (serialized, oid) = tree.digest()?;
     write_obj(&odb.path, &oid, &serialized)?;
     Ok(oid)
 }
 
-pub fn transfer(odb: &Odb, wroot: &Path, obj: &Object) -> Result<String, TransferError> {
+pub fn transfer(
+    odb: &Odb,
+    wroot: &Path,
+    obj: &Object,
+    sources: &Sources,
+) -> Result<Oid, TransferError> {
     match obj {
         Object::HashFile(hf) => {
             transfer_obj(&odb.path, wroot, hf)?;
-            Ok(hf.clone())
+            Ok(*hf)
         }
-        Object::Tree(t) => Ok(transfer_tree(odb, wroot, t)?),
+        Object::Tree(t) => Ok(transfer_tree(odb, wroot, t, sources)?),
     }
 }
@@ -1,9 +1,12 @@
 use camino::{Utf8Path, Utf8PathBuf};
-use dvc_data::Object::Tree;
+use dvc_data::Object::{HashFile, Tree};
+use dvc_data::build::{BuildError, BuildOptions, SymlinkPolicy};
 use dvc_data::ignore::get_ignore;
 use dvc_data::objects::TreeEntry;
+use dvc_data::odb::oid_to_path;
 use dvc_data::repo::Repo;
-use dvc_data::{build, create_pool};
+use dvc_data::state::{StateHash, StateValue};
+use dvc_data::{build, create_pool, transfer};
 use std::fs;
 use tempfile::tempdir;
 
@@ -26,14 +29,16 @@ pub fn test_build() -> Result<(), Box<dyn std::error::Error>> {
 
     let abspath = t!(fs::canonicalize(dir.path()));
     let ignore = get_ignore(&repo.root, abspath.parent().unwrap())?;
-    let (obj, size) = build(
+    let (obj, size, report) = build(
         &repo.odb,
         <&Utf8Path>::try_from(dir.path())?,
         state,
         &ignore,
         threads,
+        &BuildOptions::default(),
     )?;
     assert_eq!(size, 10);
+    assert!(report.is_empty());
 
     let Tree(t) = obj else {
         panic!("Should have returned tree")
@@ -44,19 +49,296 @@ pub fn test_build() -> Result<(), Box<dyn std::error::Error>> {
         text,
         r#"[{"md5": "e5a81dd70644b5534aae9f7c32055ec3", "relpath": "data/bar"}, {"md5": "eceec35e3f3dd774244de59b1094cc59", "relpath": "data/baz"}]"#
     );
-    assert_eq!(oid, "a187d325e83704a3fad49b2f2ab67d20.dir");
+    assert_eq!(oid.to_string(), "a187d325e83704a3fad49b2f2ab67d20.dir");
     assert_eq!(
-        t.entries,
+        t.iter().collect::<Vec<_>>(),
         vec![
             TreeEntry {
                 relpath: Utf8PathBuf::from("data/bar"),
-                oid: "e5a81dd70644b5534aae9f7c32055ec3".to_owned()
+                oid: "e5a81dd70644b5534aae9f7c32055ec3".parse()?
             },
             TreeEntry {
                 relpath: Utf8PathBuf::from("data/baz"),
-                oid: "eceec35e3f3dd774244de59b1094cc59".to_owned()
+                oid: "eceec35e3f3dd774244de59b1094cc59".parse()?
             },
         ]
     );
     Ok(())
 }
+
+#[test]
+pub fn test_build_normalizes_relpaths() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let data_dir = dir.path().join("data");
+    t!(fs::create_dir_all(data_dir.join("cafe\u{301}")));
+    write_to_temp_file(&data_dir.join("cafe\u{301}"), "menu.txt", "menu\n");
+    // a composed name holding a decomposed one, and a Windows separator
+    t!(fs::create_dir_all(data_dir.join("caf\u{e9}s")));
+    write_to_temp_file(&data_dir.join("caf\u{e9}s"), "e\u{301}.txt", "e\n");
+    write_to_temp_file(&data_dir, "win\\dows.txt", "windows\n");
+    t!(fs::create_dir(dir.path().join(".dvc")));
+    // DVC rejects unknown sections in its own config, so they are ignored
+    write_to_temp_file(
+        &dir.path().join(".dvc"),
+        "config",
+        "[tree]\nnormalize_unicode = true\n",
+    );
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    assert!(!repo.odb.normalization.nfc);
+
+    write_to_temp_file(
+        &dir.path().join(".dvc"),
+        "dvc-data.ini",
+        "[tree]\nnormalize_unicode = true\nnormalize_backslashes = true\n",
+    );
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    let abspath = t!(fs::canonicalize(dir.path()));
+    let ignore = get_ignore(&repo.root, &abspath)?;
+    let (obj, _, report) = build(
+        &repo.odb,
+        <&Utf8Path>::try_from(data_dir.as_path())?,
+        None,
+        &ignore,
+        1,
+        &BuildOptions::default(),
+    )?;
+    let Tree(ref t) = obj else {
+        panic!("Should have returned tree")
+    };
+    let relpaths: Vec<_> = t.iter().map(|entry| entry.relpath).collect();
+    assert_eq!(
+        relpaths,
+        [
+            Utf8PathBuf::from("caf\u{e9}/menu.txt"),
+            Utf8PathBuf::from("caf\u{e9}s/\u{e9}.txt"),
+            Utf8PathBuf::from("win/dows.txt"),
+        ]
+    );
+
+    // the files are still read from their names on disk
+    transfer(&repo.odb, &data_dir, &obj, &report.sources)?;
+    for entry in t.iter() {
+        assert!(oid_to_path(&repo.odb.path, &entry.oid).is_file());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_build_skips_non_utf8_names() -> Result<(), Box<dyn std::error::Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = t!(tempdir());
+    let data_dir = dir.path().join("data");
+    t!(fs::create_dir_all(data_dir.join("sub")));
+    write_to_temp_file(&data_dir, "bar", "bar\n");
+    let bad = data_dir.join("sub").join(OsStr::from_bytes(b"caf\xe9.txt"));
+    t!(fs::write(&bad, "latin-1\n"));
+
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    let abspath = t!(fs::canonicalize(dir.path()));
+    let ignore = get_ignore(&repo.root, &abspath)?;
+    let (obj, size, report) = build(
+        &repo.odb,
+        <&Utf8Path>::try_from(data_dir.as_path())?,
+        None,
+        &ignore,
+        1,
+        &BuildOptions::default(),
+    )?;
+    assert_eq!(size, 5);
+    assert_eq!(report.non_utf8, [bad]);
+    let Tree(t) = obj else {
+        panic!("Should have returned tree")
+    };
+    let relpaths: Vec<_> = t.iter().map(|entry| entry.relpath).collect();
+    assert_eq!(relpaths, [Utf8PathBuf::from("bar")]);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_build_continue_on_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let data_dir = dir.path().join("data");
+    t!(fs::create_dir(&data_dir));
+    write_to_temp_file(&data_dir, "bar", "bar\n");
+    let broken = data_dir.join("broken");
+    t!(std::os::unix::fs::symlink(
+        data_dir.join("missing"),
+        &broken
+    ));
+
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    let abspath = t!(fs::canonicalize(dir.path()));
+    let ignore = get_ignore(&repo.root, &abspath)?;
+    let root = <&Utf8Path>::try_from(data_dir.as_path())?;
+
+    let err = build(
+        &repo.odb,
+        root,
+        Some(&repo.state),
+        &ignore,
+        1,
+        &BuildOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, BuildError::WalkError(_)), "{err}");
+
+    let options = BuildOptions {
+        continue_on_error: true,
+        ..BuildOptions::default()
+    };
+    let (obj, size, report) = build(&repo.odb, root, Some(&repo.state), &ignore, 1, &options)?;
+    assert_eq!(size, 5);
+    let paths: Vec<_> = report.errors.iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, [&broken]);
+    let Tree(t) = obj else {
+        panic!("Should have returned tree")
+    };
+    let relpaths: Vec<_> = t.iter().map(|entry| entry.relpath).collect();
+    assert_eq!(relpaths, [Utf8PathBuf::from("bar")]);
+    assert!(repo.state.get(root.join("bar").as_str())?.is_some());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn test_build_continue_on_hash_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let data_dir = dir.path().join("data");
+    t!(fs::create_dir(&data_dir));
+    write_to_temp_file(&data_dir, "bar", "bar\n");
+    // walked as a regular file, but reading it fails even when running as root
+    let unreadable = data_dir.join("mem");
+    t!(std::os::unix::fs::symlink("/proc/self/mem", &unreadable));
+
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    let abspath = t!(fs::canonicalize(dir.path()));
+    let ignore = get_ignore(&repo.root, &abspath)?;
+    let root = <&Utf8Path>::try_from(data_dir.as_path())?;
+
+    let err = build(
+        &repo.odb,
+        root,
+        Some(&repo.state),
+        &ignore,
+        1,
+        &BuildOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, BuildError::Io(_)), "{err}");
+    // the files that were hashed are saved even though the build failed
+    assert!(repo.state.get(root.join("bar").as_str())?.is_some());
+
+    let options = BuildOptions {
+        continue_on_error: true,
+        ..BuildOptions::default()
+    };
+    let (obj, size, report) = build(&repo.odb, root, Some(&repo.state), &ignore, 1, &options)?;
+    assert_eq!(size, 5);
+    let paths: Vec<_> = report.errors.iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, [&unreadable]);
+    let Tree(t) = obj else {
+        panic!("Should have returned tree")
+    };
+    let relpaths: Vec<_> = t.iter().map(|entry| entry.relpath).collect();
+    assert_eq!(relpaths, [Utf8PathBuf::from("bar")]);
+    assert!(repo.state.get(unreadable.to_str().unwrap())?.is_none());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_build_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::symlink;
+
+    let dir = t!(tempdir());
+    let data_dir = dir.path().join("data");
+    t!(fs::create_dir(&data_dir));
+    write_to_temp_file(&data_dir, "bar", "bar\n");
+    t!(symlink(data_dir.join("bar"), data_dir.join("link")));
+
+    let repo = t!(Repo::open(Some(dir.path().to_path_buf())));
+    // the contents do not match the oid, so these are not re-hashed
+    let cached = "acbd18db4cc2f85cedef654fccc4a4d8".parse()?;
+    let object = oid_to_path(&repo.odb.path, &cached);
+    t!(fs::create_dir_all(object.parent().unwrap()));
+    t!(fs::write(&object, "cached\n"));
+    t!(symlink(&object, data_dir.join("symlinked")));
+    t!(fs::hard_link(&object, data_dir.join("hardlinked")));
+
+    let abspath = t!(fs::canonicalize(dir.path()));
+    let ignore = get_ignore(&repo.root, &abspath)?;
+    let root = <&Utf8Path>::try_from(data_dir.as_path())?;
+
+    // a hardlink is only recognized through the oid recorded for its path,
+    // e.g. before `add` replaced the file with a link to its object
+    let options = BuildOptions::default();
+    let (obj, _, _) = build(&repo.odb, root, None, &ignore, 1, &options)?;
+    let HashFile(hashed) = build(
+        &repo.odb,
+        &root.join("hardlinked"),
+        None,
+        &ignore,
+        1,
+        &options,
+    )?
+    .0
+    else {
+        panic!("Should have returned file")
+    };
+    assert_ne!(hashed, cached);
+    let Tree(t) = obj else {
+        panic!("Should have returned tree")
+    };
+    assert!(t.iter().any(|entry| entry.oid == hashed));
+    repo.state.set(
+        root.join("hardlinked").as_str(),
+        &StateValue {
+            checksum: "stale".to_owned(),
+            size: 7,
+            hash_info: StateHash { oid: cached },
+        },
+    )?;
+    let build_with = |symlinks| {
+        let options = BuildOptions {
+            symlinks,
+            ..BuildOptions::default()
+        };
+        build(&repo.odb, root, Some(&repo.state), &ignore, 1, &options).map(|(obj, _, _)| match obj
+        {
+            Tree(t) => t
+                .iter()
+                .map(|entry| (entry.relpath.to_string(), entry.oid.to_string()))
+                .collect::<Vec<_>>(),
+            HashFile(_) => panic!("Should have returned tree"),
+        })
+    };
+
+    let bar = ("bar", "e5a81dd70644b5534aae9f7c32055ec3");
+    let link = ("link", bar.1);
+    let hardlinked = ("hardlinked", "acbd18db4cc2f85cedef654fccc4a4d8");
+    let symlinked = ("symlinked", hardlinked.1);
+    let expected = |entries: &[(&str, &str)]| -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|(relpath, oid)| ((*relpath).to_string(), (*oid).to_string()))
+            .collect()
+    };
+    assert_eq!(
+        build_with(SymlinkPolicy::Follow)?,
+        expected(&[bar, hardlinked, link, symlinked])
+    );
+    assert_eq!(
+        build_with(SymlinkPolicy::Skip)?,
+        expected(&[bar, hardlinked, symlinked])
+    );
+    let err = build_with(SymlinkPolicy::Error).unwrap_err();
+    assert!(
+        matches!(&err, BuildError::Symlink(path) if *path == data_dir.join("link")),
+        "{err}"
+    );
+    Ok(())
+}
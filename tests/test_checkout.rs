@@ -0,0 +1,181 @@
+use camino::Utf8PathBuf;
+use dvc_data::checkout::{CheckoutError, CheckoutOptions};
+use dvc_data::checkout_obj;
+use dvc_data::objects::TreeError;
+use dvc_data::odb::Odb;
+use dvc_data::state::State;
+use dvc_data::transfer::write_obj;
+use std::fs;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::{blob_oid, odb_with_tree, write_to_temp_file};
+
+#[test]
+pub fn test_checkout_allow_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let (odb, oid) = odb_with_tree(dir.path(), &[("bar", "bar"), ("baz", "baz")], &["baz"]);
+
+    let to = Utf8PathBuf::try_from(dir.path().join("data"))?;
+    let err = checkout_obj(&odb, &oid, &to, &None, None, &CheckoutOptions::default()).unwrap_err();
+    assert!(matches!(err, CheckoutError::MissingObject(..)));
+
+    let options = CheckoutOptions {
+        allow_missing: true,
+        ..CheckoutOptions::default()
+    };
+    let result = checkout_obj(&odb, &oid, &to, &None, None, &options)?;
+    assert_eq!(result.missing, vec![(to.join("baz"), blob_oid("baz"))]);
+    assert_eq!(fs::read_to_string(to.join("bar"))?, "bar");
+    Ok(())
+}
+
+#[test]
+pub fn test_checkout_allow_missing_keeps_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let (odb, oid) = odb_with_tree(dir.path(), &[("bar", "bar"), ("baz", "baz")], &["baz"]);
+
+    let data = dir.path().join("data");
+    t!(fs::create_dir(&data));
+    write_to_temp_file(&data, "bar", "old");
+    write_to_temp_file(&data, "baz", "baz");
+    write_to_temp_file(&data, "untracked", "untracked");
+
+    let to = Utf8PathBuf::try_from(data)?;
+    let options = CheckoutOptions {
+        allow_missing: true,
+        ..CheckoutOptions::default()
+    };
+    let result = checkout_obj(&odb, &oid, &to, &None, None, &options)?;
+    assert_eq!(result.missing.len(), 1);
+    assert_eq!(fs::read_to_string(to.join("bar"))?, "bar");
+    // neither the file whose object is missing nor untracked files are removed
+    assert_eq!(fs::read_to_string(to.join("baz"))?, "baz\n");
+    assert_eq!(fs::read_to_string(to.join("untracked"))?, "untracked\n");
+    assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+    Ok(())
+}
+
+#[test]
+pub fn test_checkout_records_hashes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let (odb, oid) = odb_with_tree(dir.path(), &[("bar", "bar")], &[]);
+
+    let state = State::open_in_memory()?;
+    let to = Utf8PathBuf::try_from(dir.path().join("data"))?;
+    checkout_obj(
+        &odb,
+        &oid,
+        &to,
+        &Some(vec!["hardlink".to_owned()]),
+        Some(&state),
+        &CheckoutOptions::default(),
+    )?;
+    let recorded = state.get(to.join("bar").as_str())?.unwrap();
+    assert_eq!(recorded.hash_info.oid, blob_oid("bar"));
+    assert_eq!(recorded.size, 3);
+    Ok(())
+}
+
+#[test]
+pub fn test_checkout_verify() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let odb = Odb {
+        path: dir.path().join("cache"),
+        ..Odb::default()
+    };
+    // contents of `bar` stored under the oid of `baz`
+    let oid = blob_oid("baz");
+    t!(write_obj(&odb.path, &oid, "bar"));
+
+    let to = Utf8PathBuf::try_from(dir.path().join("baz"))?;
+    let options = CheckoutOptions {
+        verify: true,
+        ..CheckoutOptions::default()
+    };
+    let err = checkout_obj(&odb, &oid, &to, &None, None, &options).unwrap_err();
+    assert!(matches!(err, CheckoutError::CorruptedObject(..)));
+    assert!(!to.exists());
+    Ok(())
+}
+
+#[test]
+pub fn test_checkout_failure_keeps_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let (odb, oid) = odb_with_tree(dir.path(), &[("bar", "bar"), ("baz", "baz")], &["baz"]);
+
+    t!(fs::create_dir(dir.path().join("data")));
+    write_to_temp_file(&dir.path().join("data"), "bar", "old");
+
+    let to = Utf8PathBuf::try_from(dir.path().join("data"))?;
+    let result = checkout_obj(&odb, &oid, &to, &None, None, &CheckoutOptions::default());
+    assert!(result.is_err());
+    assert_eq!(fs::read_to_string(to.join("bar"))?, "old\n");
+    assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn test_checkout_restores_replaced_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = Utf8PathBuf::try_from(fs::canonicalize(dir.path())?)?;
+    // `.data.replaced/<long>` is one byte over `PATH_MAX` while
+    // `.data.staging/<long>` is not, so `<long>` is staged, but the file it
+    // replaces cannot be moved aside and the checkout fails partway
+    let len = 4095 - root.join(".data.staging/").as_str().len();
+    let mut long = String::new();
+    while len - long.len() > 255 {
+        long.push_str(&"d".repeat(200));
+        long.push('/');
+    }
+    long.push_str(&"f".repeat(len - long.len()));
+    let (odb, oid) = odb_with_tree(root.as_std_path(), &[("bar", "bar"), (&long, "new")], &[]);
+
+    let to = root.join("data");
+    let old = [("a", "a"), ("bar", "old"), (long.as_str(), "old")];
+    for (relpath, contents) in old {
+        let path = to.join(relpath);
+        t!(fs::create_dir_all(path.parent().unwrap()));
+        t!(fs::write(&path, contents));
+    }
+    let err = checkout_obj(&odb, &oid, &to, &None, None, &CheckoutOptions::default()).unwrap_err();
+    assert!(matches!(err, CheckoutError::Io(_)), "{err}");
+    for (relpath, contents) in old {
+        assert_eq!(fs::read_to_string(to.join(relpath))?, contents);
+    }
+    // neither the staged nor the replaced files are left behind
+    assert_eq!(fs::read_dir(&root)?.count(), 2);
+
+    // the file `a` is moved aside for the directory holding `a/c`
+    let (odb, oid) = odb_with_tree(root.as_std_path(), &[("a/c", "c"), ("bar", "bar")], &[]);
+    checkout_obj(&odb, &oid, &to, &None, None, &CheckoutOptions::default())?;
+    assert_eq!(fs::read_to_string(to.join("a/c"))?, "c");
+    assert_eq!(fs::read_to_string(to.join("bar"))?, "bar");
+    Ok(())
+}
+
+#[test]
+pub fn test_checkout_rejects_unsafe_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let (odb, _) = odb_with_tree(dir.path(), &[("bar", "bar")], &[]);
+    // a tree with such an entry cannot be built, so it is written as is
+    let tree = format!(
+        r#"[{{"md5": "{}", "relpath": "../../evil"}}]"#,
+        blob_oid("bar")
+    );
+    let oid = "a187d325e83704a3fad49b2f2ab67d20.dir".parse()?;
+    t!(write_obj(&odb.path, &oid, &tree));
+
+    let to = Utf8PathBuf::try_from(dir.path().join("ws/data"))?;
+    t!(fs::create_dir_all(dir.path().join("ws")));
+    let err = checkout_obj(&odb, &oid, &to, &None, None, &CheckoutOptions::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        CheckoutError::TreeError(TreeError::InvalidEntry(..))
+    ));
+    assert!(!dir.path().join("evil").exists());
+    assert!(!to.exists());
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use dvc_data::diff::{Sizes, Summary, diff_outputs, diff_tree, load_output_objects};
+use dvc_data::index::TrackedOutput;
+use dvc_data::models::Output;
+use dvc_data::odb::Odb;
+use dvc_data::patch::{TableSummary, is_text_path, table_summary, unified_diff};
+use std::collections::BTreeMap;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::{oid, tree};
+
+#[test]
+pub fn test_diff_serialize() -> Result<(), Box<dyn std::error::Error>> {
+    let old = tree(&[("bar", "1"), ("baz", "2"), ("foo", "3")]);
+    let new = tree(&[("bar", "1"), ("baz", "4"), ("qux", "5")]);
+    let diff = diff_tree(Some(old), Some(new));
+    assert_eq!(diff.unchanged, 1);
+    let (o2, o3, o4, o5) = (oid("2"), oid("3"), oid("4"), oid("5"));
+    assert_eq!(
+        serde_json::to_string(&diff)?,
+        format!(
+            concat!(
+                r#"[{{"path":"baz","type":"modified","old":"{}","new":"{}"}},"#,
+                r#"{{"path":"foo","type":"removed","old":"{}","new":null}},"#,
+                r#"{{"path":"qux","type":"added","old":null,"new":"{}"}}]"#
+            ),
+            o2, o4, o3, o5
+        )
+    );
+    Ok(())
+}
+
+#[test]
+pub fn test_diff_changes_grouped_by_directory() {
+    let old = tree(&[("a/b/c", "1"), ("a/z", "2"), ("a.txt", "3")]);
+    let new = tree(&[("a/b/c", "4"), ("a/y", "5"), ("b", "6")]);
+    let diff = diff_tree(Some(old), Some(new));
+    let paths: Vec<_> = diff
+        .changes()
+        .into_iter()
+        .map(|change| change.path.as_str())
+        .collect();
+    assert_eq!(paths, ["a.txt", "b", "a/y", "a/z", "a/b/c"]);
+}
+
+#[test]
+pub fn test_diff_detect_renames() {
+    let old = tree(&[("a/bar", "1"), ("a/baz", "2"), ("e1", "0"), ("e2", "0")]);
+    let new = tree(&[("b/bar", "1"), ("b/baz", "3"), ("e3", "0")]);
+    let diff = diff_tree(Some(old), Some(new)).detect_renames(1);
+    assert_eq!(
+        diff.renamed,
+        BTreeMap::from([(
+            Utf8PathBuf::from("b/bar"),
+            (Utf8PathBuf::from("a/bar"), oid("1"))
+        )])
+    );
+    // two removed files share the oid of `e3`, so they are not paired
+    assert_eq!(
+        diff.removed.keys().collect::<Vec<_>>(),
+        ["a/baz", "e1", "e2"]
+    );
+    assert_eq!(diff.added.keys().collect::<Vec<_>>(), ["b/baz", "e3"]);
+}
+
+#[test]
+pub fn test_diff_summary() {
+    let old = tree(&[
+        ("raw/r", "8"),
+        ("test/a", "1"),
+        ("test/b", "9"),
+        ("train/a", "2"),
+        ("train/b", "3"),
+    ]);
+    let new = tree(&[
+        ("test/a", "4"),
+        ("test/c", "9"),
+        ("train/c", "5"),
+        ("train/d", "6"),
+        ("train/r", "8"),
+        ("x", "7"),
+    ]);
+    let mut diff = diff_tree(Some(old), Some(new)).detect_renames(1);
+    for (path, old, new) in [
+        ("test/a", Some(10), Some(25)),
+        ("test/c", Some(7), Some(7)),
+        ("train/a", Some(100), None),
+        ("train/b", Some(100), None),
+        ("train/c", None, Some(1000)),
+        ("train/d", None, Some(1000)),
+        ("train/r", Some(500), Some(500)),
+        ("x", None, Some(1)),
+    ] {
+        diff.sizes.insert(path.into(), Sizes { old, new });
+    }
+    let summary = diff.summary(1);
+    assert_eq!(
+        summary.keys().collect::<Vec<_>>(),
+        vec!["raw/", "test/", "train/", "x"]
+    );
+    // moved from `raw/` to `train/`
+    assert_eq!(
+        summary["raw/"],
+        Summary {
+            removed: 1,
+            bytes_removed: 500,
+            ..Summary::default()
+        }
+    );
+    assert_eq!(
+        summary["train/"],
+        Summary {
+            added: 3,
+            removed: 2,
+            bytes_added: 2500,
+            bytes_removed: 200,
+            ..Summary::default()
+        }
+    );
+    // renamed within `test/`
+    assert_eq!(
+        summary["test/"],
+        Summary {
+            modified: 1,
+            renamed: 1,
+            bytes_added: 25,
+            bytes_removed: 10,
+            ..Summary::default()
+        }
+    );
+}
+
+#[test]
+pub fn test_table_summary() {
+    let old = "id,label,\"note, free\"\n1,cat,a\n2,dog,b\n";
+    let new = "id,label,score\n1,cat,0.5\n2,dog,b\n3,bird,c\n";
+    let summary = table_summary(Utf8Path::new("labels.csv"), old, new);
+    assert_eq!(
+        summary,
+        TableSummary {
+            rows_added: 2,
+            rows_removed: 1,
+            columns_added: vec!["score".to_owned()],
+            columns_removed: vec!["note, free".to_owned()],
+        }
+    );
+
+    let patch = unified_diff(Utf8Path::new("labels.csv"), old, new);
+    assert!(patch.starts_with("--- a/labels.csv\n+++ b/labels.csv\n"));
+    assert!(patch.contains("+3,bird,c\n"));
+    assert!(is_text_path(Utf8Path::new("meta/labels.YAML")));
+    assert!(!is_text_path(Utf8Path::new("images/cat.png")));
+}
+
+#[test]
+pub fn test_diff_outputs_missing_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let odb = Odb {
+        path: dir.path().join("cache"),
+        ..Odb::default()
+    };
+    let output = |oid: &str| -> Result<TrackedOutput, Box<dyn std::error::Error>> {
+        Ok(TrackedOutput {
+            dvcfile: "data.dvc".into(),
+            output: Output {
+                hash: "md5".to_owned(),
+                oid: oid.parse()?,
+                size: None,
+                nfiles: None,
+                path: "data".into(),
+            },
+        })
+    };
+    let (old_oid, new_oid) = (oid("1").to_tree(), oid("2").to_tree());
+    let old = load_output_objects(&odb, vec![output(&old_oid.to_string())?])?;
+    let new = load_output_objects(&odb, vec![output(&new_oid.to_string())?])?;
+    // neither tree is in the cache, so only the output itself is reported
+    let diff = diff_outputs(old, new);
+    assert_eq!(
+        diff.modified,
+        BTreeMap::from([(Utf8PathBuf::from("data/"), (old_oid, new_oid))])
+    );
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use camino::Utf8PathBuf;
+use dvc_data::git::{GitError, output_history, outputs_at_rev};
+use dvc_data::odb::Odb;
+use dvc_data::status::status_git;
+use git2::{Repository, Signature};
+use std::fs;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::write_to_temp_file;
+
+fn commit_all(repo: &Repository, message: &str) -> Result<(), git2::Error> {
+    let mut index = repo.index()?;
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let sig = Signature::now("dvc", "dvc@example.com")?;
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)?;
+    Ok(())
+}
+
+#[test]
+pub fn test_outputs_at_rev() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = fs::canonicalize(dir.path())?;
+    let git_repo = Repository::init(&root)?;
+
+    t!(fs::create_dir(root.join("sub")));
+    let dvcfile = "outs:\n- md5: e5a81dd70644b5534aae9f7c32055ec3\n  hash: md5\n  path: bar";
+    write_to_temp_file(&root.join("sub"), "bar.dvc", dvcfile);
+    commit_all(&git_repo, "first")?;
+
+    let dvcfile = "outs:\n- md5: eceec35e3f3dd774244de59b1094cc59\n  hash: md5\n  path: bar";
+    write_to_temp_file(&root.join("sub"), "bar.dvc", dvcfile);
+    commit_all(&git_repo, "second")?;
+
+    for (rev, oid) in [
+        ("HEAD~1", "e5a81dd70644b5534aae9f7c32055ec3"),
+        ("HEAD", "eceec35e3f3dd774244de59b1094cc59"),
+    ] {
+        let outputs = outputs_at_rev(&git_repo, rev, &root)?;
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].path(), Utf8PathBuf::from("sub/bar"));
+        assert_eq!(outputs[0].output.oid, oid.parse()?);
+    }
+
+    let outputs = outputs_at_rev(&git_repo, "HEAD", &root.join("sub"))?;
+    assert_eq!(outputs[0].path(), Utf8PathBuf::from("bar"));
+
+    let outside = t!(tempdir());
+    let outside = fs::canonicalize(outside.path())?;
+    let err = outputs_at_rev(&git_repo, "HEAD", &outside).unwrap_err();
+    assert!(matches!(err, GitError::OutsideWorkdir(..)), "{err}");
+    Ok(())
+}
+
+#[test]
+pub fn test_status_git() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = fs::canonicalize(dir.path())?;
+    let git_repo = Repository::init(&root)?;
+    let odb = Odb {
+        path: root.join(".dvc/cache/files/md5"),
+        ..Odb::default()
+    };
+    let dvcfile = Utf8PathBuf::try_from(root.join("bar.dvc"))?;
+
+    let contents = "outs:\n- md5: e5a81dd70644b5534aae9f7c32055ec3\n  hash: md5\n  path: bar";
+    write_to_temp_file(&root, "bar.dvc", contents);
+    // not committed yet, with no commits at all
+    let diff = status_git(&git_repo, &odb, "HEAD", &dvcfile)?;
+    assert_eq!(
+        diff.added.keys().collect::<Vec<_>>(),
+        [&dvcfile.with_extension("")]
+    );
+    commit_all(&git_repo, "first")?;
+
+    let contents = "outs:\n- md5: eceec35e3f3dd774244de59b1094cc59\n  hash: md5\n  path: bar";
+    write_to_temp_file(&root, "bar.dvc", contents);
+    commit_all(&git_repo, "second")?;
+    assert!(status_git(&git_repo, &odb, "HEAD", &dvcfile)?.is_empty());
+    let diff = status_git(&git_repo, &odb, "HEAD~1", &dvcfile)?;
+    assert_eq!(
+        diff.modified[&dvcfile.with_extension("")],
+        (
+            "e5a81dd70644b5534aae9f7c32055ec3".parse()?,
+            "eceec35e3f3dd774244de59b1094cc59".parse()?
+        )
+    );
+    assert!(status_git(&git_repo, &odb, "no-such-rev", &dvcfile).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_output_history() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = fs::canonicalize(dir.path())?;
+    let git_repo = Repository::init(&root)?;
+    let lockfile = |model: &str, metrics: &str| {
+        format!(
+            "schema: '2.0'\nstages:\n  train:\n    outs:\n    - path: model\n      hash: md5\n      md5: {model:0>32}\n  eval:\n    outs:\n    - path: metrics\n      hash: md5\n      md5: {metrics:0>32}"
+        )
+    };
+
+    write_to_temp_file(&root, "dvc.lock", &lockfile("1", "a"));
+    commit_all(&git_repo, "first")?;
+    write_to_temp_file(&root, "dvc.lock", &lockfile("1", "b"));
+    commit_all(&git_repo, "only metrics")?;
+    write_to_temp_file(&root, "dvc.lock", &lockfile("2", "b"));
+    commit_all(&git_repo, "retrain")?;
+
+    let versions = output_history(&git_repo, "HEAD", "dvc.lock".into(), "model".into())?;
+    let history: Vec<_> = versions
+        .iter()
+        .map(|v| {
+            (
+                v.message.as_str(),
+                v.output.as_ref().map(|out| out.oid.to_string()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        history,
+        [
+            ("retrain", Some(format!("{:0>32}", 2))),
+            ("first", Some(format!("{:0>32}", 1)))
+        ]
+    );
+
+    let versions = output_history(&git_repo, "HEAD~1", "dvc.lock".into(), "metrics".into())?;
+    assert_eq!(versions.len(), 2);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use camino::Utf8PathBuf;
+use dvc_data::index::{collect_outputs, find_dvcfiles};
+use std::fs;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::write_to_temp_file;
+
+#[test]
+pub fn test_find_dvcfiles() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = Utf8PathBuf::try_from(fs::canonicalize(dir.path())?)?;
+    for subdir in ["sub", "ignored", ".git"] {
+        t!(fs::create_dir(root.join(subdir)));
+    }
+    let dvcfile = "outs:\n- md5: e5a81dd70644b5534aae9f7c32055ec3\n  hash: md5\n  path: bar";
+    write_to_temp_file(root.as_std_path(), "bar.dvc", dvcfile);
+    write_to_temp_file(&root.join("sub").into_std_path_buf(), "bar.dvc", dvcfile);
+    write_to_temp_file(
+        &root.join("ignored").into_std_path_buf(),
+        "bar.dvc",
+        dvcfile,
+    );
+    write_to_temp_file(&root.join(".git").into_std_path_buf(), "bar.dvc", dvcfile);
+    write_to_temp_file(root.as_std_path(), ".dvcignore", "ignored");
+    let lockfile = "schema: '2.0'\nstages:\n  train:\n    cmd: train\n    outs:\n    - path: model\n      hash: md5\n      md5: eceec35e3f3dd774244de59b1094cc59";
+    write_to_temp_file(root.as_std_path(), "dvc.lock", lockfile);
+
+    let dvcfiles = find_dvcfiles(&root)?;
+    assert_eq!(
+        dvcfiles,
+        vec![
+            root.join("bar.dvc"),
+            root.join("dvc.lock"),
+            root.join("sub/bar.dvc")
+        ]
+    );
+
+    let paths: Vec<_> = collect_outputs(&dvcfiles)?
+        .iter()
+        .map(|out| (out.path(), out.output.oid.to_string()))
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            (
+                root.join("bar"),
+                "e5a81dd70644b5534aae9f7c32055ec3".to_owned()
+            ),
+            (
+                root.join("model"),
+                "eceec35e3f3dd774244de59b1094cc59".to_owned()
+            ),
+            (
+                root.join("sub/bar"),
+                "e5a81dd70644b5534aae9f7c32055ec3".to_owned()
+            ),
+        ]
+    );
+    Ok(())
+}
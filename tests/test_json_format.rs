@@ -0,0 +1,50 @@
+use camino::Utf8PathBuf;
+use dvc_data::Tree;
+use dvc_data::json_format::to_string;
+use dvc_data::objects::{Oid, TreeEntry};
+use std::collections::BTreeMap;
+
+#[test]
+pub fn test_to_string_separators() -> Result<(), Box<dyn std::error::Error>> {
+    let value = BTreeMap::from([("a,b", vec!["c:d", "é"]), ("e", vec![])]);
+    assert_eq!(to_string(&value)?, r#"{"a,b": ["c:d", "\u00e9"], "e": []}"#);
+    Ok(())
+}
+
+#[test]
+pub fn test_tree_digest_matches_dvc() -> Result<(), Box<dyn std::error::Error>> {
+    let oid: Oid = "d41d8cd98f00b204e9800998ecf8427e".parse()?;
+    let relpaths = [
+        "a,b:c.txt",
+        "ctl\u{1}",
+        "naïve/ünïcode.csv",
+        "emoji 😀",
+        "tab\there",
+        "q\"b\\s",
+        "del\u{7f}x",
+        "{x}: [1, 2]",
+    ];
+    let tree = Tree::from_entries(relpaths.into_iter().map(|relpath| TreeEntry {
+        relpath: Utf8PathBuf::from(relpath),
+        oid,
+    }))?;
+
+    // `json.dumps()` of the same entries in Python, as written by DVC
+    let expected = concat!(
+        r#"[{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "a,b:c.txt"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "ctl\u0001"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "del\u007fx"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "emoji \ud83d\ude00"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "na\u00efve/\u00fcn\u00efcode.csv"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "q\"b\\s"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "tab\there"}, "#,
+        r#"{"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "{x}: [1, 2]"}]"#,
+    );
+    let (serialized, digest) = tree.digest()?;
+    assert_eq!(serialized, expected);
+    assert_eq!(digest.to_string(), "d047d63b0a65211f352ab54076f0709c.dir");
+
+    let loaded: Tree = serde_json::from_str(expected)?;
+    assert_eq!(loaded, tree);
+    Ok(())
+}
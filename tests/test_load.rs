@@ -1,6 +1,6 @@
 use camino::Utf8PathBuf;
-use dvc_data::Tree;
-use dvc_data::objects::TreeEntry;
+use dvc_data::objects::{InvalidEntry, Normalization, Oid, TreeEntry, TreeError};
+use dvc_data::{Object, Tree};
 use std::fs;
 use tempfile::tempdir;
 
@@ -18,17 +18,224 @@ pub fn test_tree_load() -> Result<(), Box<dyn std::error::Error>> {
 
     let t = Tree::load_from(&test_dir.join("tree"))?;
     assert_eq!(
-        t.entries,
+        t.iter().collect::<Vec<_>>(),
         vec![
             TreeEntry {
                 relpath: Utf8PathBuf::from("data/bar"),
-                oid: "e5a81dd70644b5534aae9f7c32055ec3".to_owned()
+                oid: "e5a81dd70644b5534aae9f7c32055ec3".parse()?
             },
             TreeEntry {
                 relpath: Utf8PathBuf::from("data/foo/baz"),
-                oid: "eceec35e3f3dd774244de59b1094cc59".to_owned()
+                oid: "eceec35e3f3dd774244de59b1094cc59".parse()?
             },
         ]
     );
     Ok(())
 }
+
+fn oid(n: u8) -> Oid {
+    Oid::md5([n; 16])
+}
+
+#[test]
+pub fn test_tree_edit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tree = Tree::default();
+    for (n, relpath) in ["a/c", "a.txt", "a/b/d", "b"].into_iter().enumerate() {
+        let n = u8::try_from(n)?;
+        assert_eq!(tree.insert(relpath.into(), oid(n))?, None);
+    }
+    let entries: Vec<_> = tree.iter().collect();
+    let relpaths: Vec<_> = entries.iter().map(|e| e.relpath.as_str()).collect();
+    assert_eq!(relpaths, ["a.txt", "a/b/d", "a/c", "b"]);
+    let mut sorted = entries.clone();
+    sorted.sort_unstable();
+    assert_eq!(entries, sorted);
+
+    let (_, before) = tree.digest()?;
+    assert_eq!(tree.insert("b".into(), oid(9))?, Some(oid(3)));
+    assert_ne!(tree.digest()?.1, before);
+    assert_eq!(tree.insert("b".into(), oid(3))?, Some(oid(9)));
+    assert_eq!(tree.digest()?.1, before);
+
+    assert_eq!(tree.remove("a.txt".into()), Some(oid(1)));
+    assert_eq!(tree.remove("a.txt".into()), None);
+    let removed: Vec<_> = tree
+        .remove_dir("a".into())
+        .into_iter()
+        .map(|e| e.oid)
+        .collect();
+    assert_eq!(removed, [oid(2), oid(0)]);
+    assert_eq!(tree.len(), 1);
+    Ok(())
+}
+
+#[test]
+pub fn test_tree_replace_path() -> Result<(), Box<dyn std::error::Error>> {
+    let mut tree = Tree::default();
+    for (n, relpath) in ["a/b", "a/c", "d/e"].into_iter().enumerate() {
+        tree.insert(relpath.into(), oid(u8::try_from(n)?))?;
+    }
+
+    // the file `a/b` is replaced by a directory holding `a/b/c`
+    let removed = tree.replace_path("a/b/c".into(), Some(&Object::HashFile(oid(7))))?;
+    assert_eq!(
+        removed,
+        [TreeEntry {
+            relpath: "a/b".into(),
+            oid: oid(0)
+        }]
+    );
+    let relpaths: Vec<_> = tree.iter().map(|e| e.relpath).collect();
+    assert_eq!(relpaths, ["a/b/c", "a/c", "d/e"]);
+
+    let mut subtree = Tree::default();
+    subtree.insert("f".into(), oid(8))?;
+    let removed = tree.replace_path("d".into(), Some(&Object::Tree(subtree)))?;
+    assert_eq!(removed.len(), 1);
+    let entries: Vec<_> = tree.iter().map(|e| (e.relpath, e.oid)).collect();
+    assert_eq!(entries[2], ("d/f".into(), oid(8)));
+
+    // a path that no longer exists only removes what is recorded under it
+    let removed = tree.replace_path("a/c/x".into(), None)?;
+    assert!(removed.is_empty());
+    assert_eq!(tree.len(), 3);
+    Ok(())
+}
+
+#[test]
+pub fn test_tree_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let contents = r#"[{"md5": "e5a81dd70644b5534aae9f7c32055ec3", "relpath": "data/bar"}, {"md5": "eceec35e3f3dd774244de59b1094cc59", "relpath": "data/foo/baz"}, {"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "top"}]"#;
+    write_to_temp_file(dir.path(), "tree", contents);
+    let t = Tree::load_from(&dir.path().join("tree"))?;
+    assert_eq!(t.len(), 3);
+    assert_eq!(t.serialize()?, contents);
+    Ok(())
+}
+
+#[test]
+pub fn test_tree_sorted_like_dvc() -> Result<(), Box<dyn std::error::Error>> {
+    // DVC sorts by relpath as a string, where `-` and `.` come before `/`
+    let dir = t!(tempdir());
+    let contents = r#"[{"md5": "eceec35e3f3dd774244de59b1094cc59", "relpath": "data-v2.csv"}, {"md5": "d41d8cd98f00b204e9800998ecf8427e", "relpath": "data.csv"}, {"md5": "e5a81dd70644b5534aae9f7c32055ec3", "relpath": "data/x.csv"}]"#;
+    write_to_temp_file(dir.path(), "tree", contents);
+    let t = Tree::load_from(&dir.path().join("tree"))?;
+    assert_eq!(t.serialize()?, contents);
+
+    let mut reversed: Vec<_> = t.iter().collect();
+    reversed.reverse();
+    let rebuilt = Tree::from_entries(reversed.clone())?;
+    assert_eq!(
+        rebuilt.digest()?.1.to_string(),
+        "a1669dd5b02eca64cae9c7d2ab7feb6e.dir"
+    );
+
+    let mut edited = Tree::default();
+    for entry in reversed {
+        edited.insert(entry.relpath, entry.oid)?;
+    }
+    assert_eq!(edited.serialize()?, contents);
+    let removed: Vec<_> = edited
+        .remove_dir("data".into())
+        .into_iter()
+        .map(|e| e.relpath)
+        .collect();
+    assert_eq!(removed, ["data/x.csv"]);
+    assert_eq!(edited.len(), 2);
+    Ok(())
+}
+
+#[test]
+pub fn test_tree_load_invalid_oid() {
+    let dir = t!(tempdir());
+    let contents = r#"[{"md5": "../../../etc/passwd", "relpath": "bar"}]"#;
+    write_to_temp_file(dir.path(), "tree", contents);
+    let err = Tree::load_from(&dir.path().join("tree")).unwrap_err();
+    assert!(matches!(err, TreeError::Json(_)));
+}
+
+#[test]
+pub fn test_tree_load_rejects_invalid_entries() {
+    let dir = t!(tempdir());
+    let md5 = "e5a81dd70644b5534aae9f7c32055ec3";
+    for (relpaths, path, reason) in [
+        (&["../evil"][..], "../evil", InvalidEntry::UnsafeRelpath),
+        (
+            &["a/../../evil"],
+            "a/../../evil",
+            InvalidEntry::UnsafeRelpath,
+        ),
+        (&["/etc/passwd"], "/etc/passwd", InvalidEntry::UnsafeRelpath),
+        (&["a//b"], "a//b", InvalidEntry::UnsafeRelpath),
+        (&["./a"], "./a", InvalidEntry::UnsafeRelpath),
+        (&[""], "", InvalidEntry::UnsafeRelpath),
+        (&["a", "b", "b"], "b", InvalidEntry::Duplicate),
+        (&["b", "a"], "a", InvalidEntry::Unsorted),
+        (&["a/b", "a.txt"], "a.txt", InvalidEntry::Unsorted),
+    ] {
+        let entries: Vec<_> = relpaths
+            .iter()
+            .map(|relpath| format!(r#"{{"md5": "{md5}", "relpath": "{relpath}"}}"#))
+            .collect();
+        write_to_temp_file(dir.path(), "tree", &format!("[{}]", entries.join(", ")));
+        let err = Tree::load_from(&dir.path().join("tree")).unwrap_err();
+        assert!(
+            matches!(&err, TreeError::InvalidEntry(p, r) if p == path && *r == reason),
+            "{relpaths:?}: {err}"
+        );
+    }
+
+    let mut tree = Tree::default();
+    assert!(matches!(
+        tree.insert("../evil".into(), oid(0)),
+        Err(TreeError::InvalidEntry(_, InvalidEntry::UnsafeRelpath))
+    ));
+    let duplicates = ["a", "a"].map(|relpath| TreeEntry {
+        relpath: relpath.into(),
+        oid: oid(0),
+    });
+    assert!(matches!(
+        Tree::from_entries(duplicates),
+        Err(TreeError::InvalidEntry(_, InvalidEntry::Duplicate))
+    ));
+}
+
+#[test]
+pub fn test_tree_normalize() -> Result<(), Box<dyn std::error::Error>> {
+    let tree = Tree::from_entries(
+        ["data\\Cafe\u{301}.txt", "data\\b", "data/CAFÉ.TXT"]
+            .into_iter()
+            .enumerate()
+            .map(|(n, relpath)| TreeEntry {
+                relpath: relpath.into(),
+                oid: oid(u8::try_from(n).unwrap()),
+            }),
+    )?;
+    assert_eq!(tree.clone().normalize(Normalization::default())?, tree);
+    assert!(tree.case_collisions().is_empty());
+
+    let normalized = tree.normalize(Normalization {
+        backslashes: true,
+        nfc: true,
+    })?;
+    let relpaths: Vec<_> = normalized.iter().map(|entry| entry.relpath).collect();
+    assert_eq!(relpaths, ["data/CAFÉ.TXT", "data/Café.txt", "data/b"]);
+    assert_eq!(
+        normalized.case_collisions(),
+        [["data/CAFÉ.TXT", "data/Café.txt"]]
+    );
+
+    // both spellings of the same name collapse into one relpath
+    let tree = Tree::from_entries(["é", "e\u{301}"].map(|relpath| TreeEntry {
+        relpath: relpath.into(),
+        oid: oid(0),
+    }))?;
+    assert!(matches!(
+        tree.normalize(Normalization {
+            backslashes: false,
+            nfc: true,
+        }),
+        Err(TreeError::InvalidEntry(_, InvalidEntry::Duplicate))
+    ));
+    Ok(())
+}
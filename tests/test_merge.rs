@@ -0,0 +1,100 @@
+use camino::Utf8PathBuf;
+use dvc_data::merge::{MergeError, merge_dvcfiles, merge_trees};
+use dvc_data::objects::Oid;
+use dvc_data::odb::{Odb, oid_to_path};
+use dvc_data::transfer::write_obj;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::{tree, write_to_temp_file};
+
+fn dvcfile(oid: &Oid) -> String {
+    format!("outs:\n- md5: {oid}\n  hash: md5\n  path: data\n")
+}
+
+#[test]
+pub fn test_merge_trees() {
+    let base = tree(&[("a", "1"), ("b", "2"), ("c", "3")]);
+    let ours = tree(&[("a", "1"), ("b", "4"), ("c", "3"), ("d", "5")]);
+    let theirs = tree(&[("a", "6"), ("b", "2"), ("e", "7")]);
+    let merged = merge_trees(&base, &ours, &theirs).unwrap();
+    assert_eq!(
+        merged,
+        tree(&[("a", "6"), ("b", "4"), ("d", "5"), ("e", "7")])
+    );
+
+    let theirs = tree(&[("a", "1"), ("b", "8"), ("c", "3")]);
+    let conflicts = merge_trees(&base, &ours, &theirs).unwrap_err();
+    assert_eq!(conflicts, [Utf8PathBuf::from("b")]);
+}
+
+#[test]
+pub fn test_merge_dvcfiles() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let odb = Odb {
+        path: dir.path().join("cache"),
+        ..Odb::default()
+    };
+    let mut oids = Vec::new();
+    for t in [
+        tree(&[("a", "e5a81dd70644b5534aae9f7c32055ec3")]),
+        tree(&[
+            ("a", "e5a81dd70644b5534aae9f7c32055ec3"),
+            ("b", "eceec35e3f3dd774244de59b1094cc59"),
+        ]),
+        tree(&[
+            ("a", "e5a81dd70644b5534aae9f7c32055ec3"),
+            ("c", "eceec35e3f3dd774244de59b1094cc59"),
+        ]),
+    ] {
+        let (serialized, oid) = t.digest()?;
+        write_obj(&odb.path, &oid, &serialized)?;
+        oids.push(oid);
+    }
+    let path = oid_to_path(&odb.path, &"e5a81dd70644b5534aae9f7c32055ec3".parse()?);
+    t!(std::fs::create_dir_all(path.parent().unwrap()));
+    write_to_temp_file(path.parent().unwrap(), path.file_name().unwrap(), "a");
+
+    let (base, ours, theirs) = (dvcfile(&oids[0]), dvcfile(&oids[1]), dvcfile(&oids[2]));
+    // only one side changed
+    assert_eq!(merge_dvcfiles(&odb, &base, &base, &theirs)?, theirs);
+    assert_eq!(merge_dvcfiles(&odb, &base, &ours, &base)?, ours);
+
+    let merged = merge_dvcfiles(&odb, &base, &ours, &theirs)?;
+    let expected = tree(&[
+        ("a", "e5a81dd70644b5534aae9f7c32055ec3"),
+        ("b", "eceec35e3f3dd774244de59b1094cc59"),
+        ("c", "eceec35e3f3dd774244de59b1094cc59"),
+    ]);
+    let (_, oid) = expected.digest()?;
+    assert!(merged.contains(&format!("md5: {oid}")));
+    assert!(merged.contains("nfiles: 3"));
+    assert!(oid_to_path(&odb.path, &oid).is_file());
+
+    // keys other than the hash, size and file count are kept, and the size
+    // is dropped as not every object is in the cache
+    let extra = |oid: &Oid| {
+        format!(
+            "desc: data\nfrozen: true\nwdir: ..\ndeps:\n- path: src\nouts:\n- md5: {oid}\n  size: 1\n  nfiles: 1\n  hash: md5\n  path: data\n  cache: true\n  remote: store\n  push: false\nmeta:\n  owner: me\n"
+        )
+    };
+    let merged = merge_dvcfiles(&odb, &base, &extra(&oids[1]), &theirs)?;
+    assert_eq!(
+        merged,
+        format!(
+            "desc: data\nfrozen: true\nwdir: ..\ndeps:\n- path: src\nouts:\n- md5: {oid}\n  nfiles: 3\n  hash: md5\n  path: data\n  cache: true\n  remote: store\n  push: false\nmeta:\n  owner: me\n"
+        )
+    );
+
+    // both sides added `b` with different contents
+    let theirs = tree(&[
+        ("a", "e5a81dd70644b5534aae9f7c32055ec3"),
+        ("b", "0123456789abcdef0123456789abcdef"),
+    ]);
+    let (serialized, theirs_oid) = theirs.digest()?;
+    write_obj(&odb.path, &theirs_oid, &serialized)?;
+    let err = merge_dvcfiles(&odb, &base, &ours, &dvcfile(&theirs_oid)).unwrap_err();
+    assert!(matches!(err, MergeError::Conflict(_, paths) if paths == ["b"]));
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use dvc_data::objects::Oid;
+use dvc_data::oid::{HashAlgorithm, OidError};
+
+#[test]
+pub fn test_oid_parse() -> Result<(), Box<dyn std::error::Error>> {
+    let oid: Oid = "e5a81dd70644b5534aae9f7c32055ec3".parse()?;
+    assert_eq!(oid.algorithm(), HashAlgorithm::Md5);
+    assert!(!oid.is_tree());
+    assert_eq!(oid.digest()[..2], [0xe5, 0xa8]);
+    assert_eq!(oid.to_string(), "e5a81dd70644b5534aae9f7c32055ec3");
+
+    let tree: Oid = "e5a81dd70644b5534aae9f7c32055ec3.dir".parse()?;
+    assert!(tree.is_tree());
+    assert_eq!(tree.digest(), oid.digest());
+    assert_eq!(tree, oid.to_tree());
+    assert_eq!(tree.hex(), oid.to_string());
+    assert_eq!(tree.to_string(), "e5a81dd70644b5534aae9f7c32055ec3.dir");
+    assert!(oid < tree);
+
+    for invalid in [
+        "",
+        ".dir",
+        "e5",
+        "E5A81DD70644B5534AAE9F7C32055EC3",
+        "e5a81dd70644b5534aae9f7c32055ec3.txt",
+        "e5a81dd70644b5534aae9f7c32055ec3e5",
+        "../../etc/passwd",
+        "e5a81dd70644b5534aae9f7c32055ecé",
+    ] {
+        assert_eq!(
+            invalid.parse::<Oid>(),
+            Err(OidError::InvalidMd5(invalid.to_owned()))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+pub fn test_oid_serde() -> Result<(), Box<dyn std::error::Error>> {
+    let oid: Oid = serde_json::from_str(r#""a187d325e83704a3fad49b2f2ab67d20.dir""#)?;
+    assert!(oid.is_tree());
+    assert_eq!(
+        serde_json::to_string(&oid)?,
+        r#""a187d325e83704a3fad49b2f2ab67d20.dir""#
+    );
+    assert!(serde_json::from_str::<Oid>(r#""a1""#).is_err());
+    assert!(serde_yaml::from_str::<dvc_data::Output>("md5: a1\nhash: md5\npath: data").is_err());
+    Ok(())
+}
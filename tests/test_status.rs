@@ -0,0 +1,177 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use dvc_data::index::TrackedOutput;
+use dvc_data::models::Output;
+use dvc_data::odb::Odb;
+use dvc_data::status::{CloudState, OutputState, cloud_status, missing_objects, status_all};
+use std::collections::BTreeMap;
+use std::fs;
+use tempfile::tempdir;
+
+mod utils;
+
+use utils::write_to_temp_file;
+
+#[test]
+pub fn test_missing_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let odb = Odb {
+        path: dir.path().join("cache"),
+        ..Odb::default()
+    };
+    t!(fs::create_dir_all(odb.path.join("e5")));
+    t!(fs::create_dir_all(odb.path.join("a1")));
+    write_to_temp_file(
+        &odb.path.join("e5"),
+        "a81dd70644b5534aae9f7c32055ec3",
+        "bar",
+    );
+    let tree = r#"[{"md5": "e5a81dd70644b5534aae9f7c32055ec3", "relpath": "bar"}, {"md5": "eceec35e3f3dd774244de59b1094cc59", "relpath": "sub/baz"}]"#;
+    write_to_temp_file(
+        &odb.path.join("a1"),
+        "87d325e83704a3fad49b2f2ab67d20.dir",
+        tree,
+    );
+
+    let data = Utf8Path::new("data");
+    let missing = missing_objects(&odb, data, &"a187d325e83704a3fad49b2f2ab67d20.dir".parse()?)?;
+    assert_eq!(
+        missing,
+        BTreeMap::from([(
+            "data/sub/baz".into(),
+            "eceec35e3f3dd774244de59b1094cc59".parse()?
+        )])
+    );
+
+    let missing = missing_objects(&odb, data, &"0123456789abcdef0123456789abcdef.dir".parse()?)?;
+    assert_eq!(
+        missing.into_keys().collect::<Vec<_>>(),
+        [Utf8Path::new("data/")]
+    );
+    assert!(missing_objects(&odb, data, &"e5a81dd70644b5534aae9f7c32055ec3".parse()?)?.is_empty());
+    Ok(())
+}
+
+#[test]
+pub fn test_cloud_status() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let odb = Odb {
+        path: dir.path().join("cache"),
+        ..Odb::default()
+    };
+    let remote = Odb {
+        path: dir.path().join("remote"),
+        ..Odb::default()
+    };
+    t!(fs::create_dir_all(odb.path.join("a1")));
+    t!(fs::create_dir_all(odb.path.join("e5")));
+    t!(fs::create_dir_all(remote.path.join("e5")));
+    t!(fs::create_dir_all(remote.path.join("ec")));
+    let tree = r#"[{"md5": "e5a81dd70644b5534aae9f7c32055ec3", "relpath": "bar"}, {"md5": "eceec35e3f3dd774244de59b1094cc59", "relpath": "baz"}, {"md5": "0123456789abcdef0123456789abcdef", "relpath": "qux"}]"#;
+    write_to_temp_file(
+        &odb.path.join("a1"),
+        "87d325e83704a3fad49b2f2ab67d20.dir",
+        tree,
+    );
+    for root in [&odb.path, &remote.path] {
+        write_to_temp_file(&root.join("e5"), "a81dd70644b5534aae9f7c32055ec3", "bar");
+    }
+    write_to_temp_file(
+        &remote.path.join("ec"),
+        "eec35e3f3dd774244de59b1094cc59",
+        "baz",
+    );
+
+    let outputs = [TrackedOutput {
+        dvcfile: "data.dvc".into(),
+        output: Output {
+            hash: "md5".to_owned(),
+            oid: "a187d325e83704a3fad49b2f2ab67d20.dir".parse()?,
+            size: None,
+            nfiles: None,
+            path: "data".into(),
+        },
+    }];
+    let statuses = cloud_status(&odb, &remote, &outputs)?;
+    let states: Vec<_> = statuses
+        .iter()
+        .map(|st| (st.path.as_str(), st.state))
+        .collect();
+    assert_eq!(
+        states,
+        [
+            ("data/", CloudState::LocalOnly),
+            ("data/bar", CloudState::Both),
+            ("data/baz", CloudState::RemoteOnly),
+            ("data/qux", CloudState::Missing),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+pub fn test_status_all() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = t!(tempdir());
+    let root = Utf8PathBuf::try_from(fs::canonicalize(dir.path())?)?;
+    let odb = Odb {
+        path: root.join("cache").into(),
+        ..Odb::default()
+    };
+    t!(fs::create_dir_all(odb.path.join("c1")));
+    write_to_temp_file(
+        &odb.path.join("c1"),
+        "57a79031e1c40f85931829bc5fc552",
+        "bar",
+    );
+    write_to_temp_file(root.as_std_path(), "bar", "bar");
+    write_to_temp_file(root.as_std_path(), "baz", "baz");
+    write_to_temp_file(root.as_std_path(), "foo", "foo");
+
+    let output = |path: &str, oid: &str| -> Result<TrackedOutput, Box<dyn std::error::Error>> {
+        Ok(TrackedOutput {
+            dvcfile: root.join(format!("{path}.dvc")),
+            output: Output {
+                hash: "md5".to_owned(),
+                oid: oid.parse()?,
+                size: None,
+                nfiles: None,
+                path: path.into(),
+            },
+        })
+    };
+    let outputs = [
+        output("bar", "c157a79031e1c40f85931829bc5fc552")?,
+        // recorded as `bar`, but the workspace file has other contents
+        output("foo", "c157a79031e1c40f85931829bc5fc552")?,
+        output("gone", "c157a79031e1c40f85931829bc5fc552")?,
+        // `baz` is in the workspace but its object is not in the cache
+        output("baz", "258622b1688250cb619f3c9ccaefb7eb")?,
+    ];
+    let statuses = status_all(&odb, None, root.as_std_path(), 1, &outputs)?;
+    let states: Vec<_> = statuses
+        .iter()
+        .map(|st| (st.path.strip_prefix(&root).unwrap().as_str(), st.state))
+        .collect();
+    assert_eq!(
+        states,
+        [
+            ("bar", OutputState::UpToDate),
+            ("foo", OutputState::Modified),
+            ("gone", OutputState::Deleted),
+            ("baz", OutputState::NotInCache),
+        ]
+    );
+    assert!(statuses[0].diff.changes().is_empty());
+    assert_eq!(
+        statuses[1].diff.modified.keys().collect::<Vec<_>>(),
+        [&root.join("foo")]
+    );
+    assert_eq!(
+        statuses[2].diff.removed.keys().collect::<Vec<_>>(),
+        [&root.join("gone")]
+    );
+    assert_eq!(
+        statuses[3].not_in_cache.keys().collect::<Vec<_>>(),
+        [&root.join("baz")]
+    );
+    Ok(())
+}
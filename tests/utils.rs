@@ -1,3 +1,12 @@
+// Shared by every test crate, each of which uses only some of the helpers.
+#![allow(dead_code)]
+
+use camino::Utf8PathBuf;
+use dvc_data::Tree;
+use dvc_data::hash::md5;
+use dvc_data::objects::{Oid, TreeEntry};
+use dvc_data::odb::Odb;
+use dvc_data::transfer::write_obj;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -18,3 +27,45 @@ pub fn write_to_temp_file<P: AsRef<Path>>(dir: &Path, file_path: P, content: &st
     t!(writeln!(file, "{}", content));
     file
 }
+
+/// Pads short oids in fixtures to valid md5s, e.g. `"1"` to `"000…01"`.
+pub fn oid(short: &str) -> Oid {
+    format!("{short:0>32}").parse().unwrap()
+}
+
+pub fn tree(entries: &[(&str, &str)]) -> Tree {
+    Tree::from_entries(entries.iter().map(|(relpath, short)| TreeEntry {
+        relpath: Utf8PathBuf::from(relpath),
+        oid: oid(short),
+    }))
+    .unwrap()
+}
+
+/// Oid of a blob holding `contents`.
+pub fn blob_oid(contents: &str) -> Oid {
+    md5(&mut contents.as_bytes()).parse().unwrap()
+}
+
+/// Creates an odb in `dir` holding a tree of `files`, given as relpath and
+/// contents, and the blobs of all files but the `missing` ones. Returns the
+/// odb and the oid of the tree.
+pub fn odb_with_tree(dir: &Path, files: &[(&str, &str)], missing: &[&str]) -> (Odb, Oid) {
+    let odb = Odb {
+        path: dir.join("cache"),
+        ..Odb::default()
+    };
+    for (relpath, contents) in files {
+        if !missing.contains(relpath) {
+            t!(write_obj(&odb.path, &blob_oid(contents), contents));
+        }
+    }
+    let tree = t!(Tree::from_entries(files.iter().map(
+        |(relpath, contents)| TreeEntry {
+            relpath: Utf8PathBuf::from(relpath),
+            oid: blob_oid(contents),
+        }
+    )));
+    let (serialized, oid) = t!(tree.digest());
+    t!(write_obj(&odb.path, &oid, &serialized));
+    (odb, oid)
+}